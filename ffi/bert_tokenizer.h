@@ -5,16 +5,90 @@
 #ifndef BERT_TOKENIZER_BERT_TOKENIZER_H
 #define BERT_TOKENIZER_BERT_TOKENIZER_H
 
-#include <cstdint>
+#include <stddef.h>
+#include <stdint.h>
 
 #if defined(__cplusplus)
 extern "C" {
 #endif
 
+typedef struct BertEncoding BertEncoding;
+
+typedef enum {
+  BERT_TOKENIZER_OK = 0,
+  BERT_TOKENIZER_NULL_POINTER = 1,
+  BERT_TOKENIZER_INVALID_ARGUMENT = 2,
+  BERT_TOKENIZER_BUFFER_TOO_SMALL = 3,
+  BERT_TOKENIZER_ENCODE_FAILED = 4,
+  BERT_TOKENIZER_LOAD_FAILED = 5,
+  // A bug in the tokenizer; the panic was caught at the library boundary.
+  BERT_TOKENIZER_PANIC = 6,
+} BertTokenizerErrorCode;
+
 void *create_full_tokenizer(const char *vocab_file, int do_lower_case);
 
 void drop_tokenizer(void *handle);
 
+// Message of the last error of the calling thread.
+const char *bert_tokenizer_get_error();
+// Code of the last call of the calling thread, BERT_TOKENIZER_OK after a
+// successful one.
+BertTokenizerErrorCode bert_tokenizer_get_error_code();
+
+// Encodes text_a, or the pair text_a, text_b unless text_b is NULL. Returns
+// NULL on error. The encoding must be released with bert_encoding_free.
+BertEncoding *bert_tokenizer_encode(const void *handle, const char *text_a,
+                                    const char *text_b, size_t max_seq_len);
+
+// Encodes like bert_tokenizer_encode into caller buffers of capacity
+// elements; NULL buffers are skipped. Returns the sequence length, or -1 on
+// error or if the sequence does not fit.
+int bert_tokenizer_encode_into(const void *handle, const char *text_a,
+                               const char *text_b, size_t max_seq_len,
+                               int64_t *input_ids, int64_t *input_mask,
+                               int64_t *segment_ids, size_t capacity);
+
+// Encodes batch_size texts in parallel into row-major
+// [batch_size, max_seq_len] buffers; NULL buffers are skipped. A row is a pair
+// where texts_b and its entry are not NULL. Returns 0, or -1 on error, in
+// which case the buffers are left untouched.
+int bert_tokenizer_encode_batch(const void *handle, const char *const *texts_a,
+                                const char *const *texts_b, size_t batch_size,
+                                size_t max_seq_len, int64_t *input_ids,
+                                int64_t *input_mask, int64_t *segment_ids);
+
+// Same as bert_tokenizer_encode_batch, with int32 buffers. Fails with
+// BERT_TOKENIZER_INVALID_ARGUMENT if a value does not fit in int32.
+int bert_tokenizer_encode_batch_i32(const void *handle,
+                                    const char *const *texts_a,
+                                    const char *const *texts_b,
+                                    size_t batch_size, size_t max_seq_len,
+                                    int32_t *input_ids, int32_t *input_mask,
+                                    int32_t *segment_ids);
+
+size_t bert_encoding_len(const BertEncoding *encoding);
+const int64_t *bert_encoding_input_ids(const BertEncoding *encoding);
+const int64_t *bert_encoding_input_mask(const BertEncoding *encoding);
+const int64_t *bert_encoding_segment_ids(const BertEncoding *encoding);
+// Copies token index of encoding into buf like snprintf. Returns the length
+// of the token, or -1 on error.
+int bert_encoding_token(const BertEncoding *encoding, size_t index, char *buf,
+                        size_t buf_len);
+void bert_encoding_free(BertEncoding *encoding);
+
+size_t bert_tokenizer_vocab_size(const void *handle);
+// Id of token, or -1 if it is not in the vocabulary or on error.
+int64_t bert_tokenizer_token_to_id(const void *handle, const char *token);
+// 1 if token is in the vocabulary, 0 if not, -1 on error.
+int bert_tokenizer_contains(const void *handle, const char *token);
+// Copies the token of id into buf like snprintf. Returns the length of the
+// token, or -1 if id is not in the vocabulary or on error.
+int bert_tokenizer_id_to_token(const void *handle, int64_t id, char *buf,
+                               size_t buf_len);
+
+// Deprecated: the results of convert_pairs are kept per thread and
+// overwritten by the next call. Use bert_tokenizer_encode instead.
+// Returns the sequence length, or -1 if the input cannot be encoded.
 int convert_pairs(void *handle, const char *text_a, const char *text_b,
                   int max_seq_len, int is_pair);
 
@@ -22,15 +96,11 @@ int64_t *get_input_ids();
 int64_t *get_input_mask();
 int64_t *get_segment_ids();
 
-const char *get_error();
-
 #if defined(__cplusplus)
 }
 #endif
 
 #if defined(__cplusplus)
-#include <string.h>
-
 #include <stdexcept>
 #include <string>
 #include <vector>
@@ -39,18 +109,11 @@ class FullTokenizer {
  private:
   void *handle;
 
- private:
-  void copy(std::vector<int64_t> &dst, int64_t *src, int len) {
-    dst.clear();
-    dst.resize(len);
-    memcpy(dst.data(), src, sizeof(int) * len);
-  }
-
  public:
   FullTokenizer(const std::string &vocab_file, int do_lower_case)
       : handle(create_full_tokenizer(vocab_file.c_str(), do_lower_case)) {
     if (handle == nullptr) {
-      const char *error = get_error();
+      const char *error = bert_tokenizer_get_error();
       throw std::runtime_error(error);
     }
   };
@@ -60,15 +123,24 @@ class FullTokenizer {
   }
 
   void convert_pairs(const std::string &text_a, const std::string &text_b,
-                     int max_seq_len, int is_pair,
+                     size_t max_seq_len, int is_pair,
                      std::vector<int64_t> &input_ids,
                      std::vector<int64_t> &input_mask,
                      std::vector<int64_t> &segment_ids) {
-    auto seq_len = ::convert_pairs(handle, text_a.c_str(), text_b.c_str(),
-                                   max_seq_len, is_pair);
-    copy(input_ids, get_input_ids(), seq_len);
-    copy(input_mask, get_input_mask(), seq_len);
-    copy(segment_ids, get_segment_ids(), seq_len);
+    BertEncoding *encoding =
+        bert_tokenizer_encode(handle, text_a.c_str(),
+                              is_pair ? text_b.c_str() : nullptr, max_seq_len);
+    if (encoding == nullptr) {
+      throw std::runtime_error(bert_tokenizer_get_error());
+    }
+    size_t seq_len = bert_encoding_len(encoding);
+    const int64_t *ids = bert_encoding_input_ids(encoding);
+    const int64_t *mask = bert_encoding_input_mask(encoding);
+    const int64_t *segments = bert_encoding_segment_ids(encoding);
+    input_ids.assign(ids, ids + seq_len);
+    input_mask.assign(mask, mask + seq_len);
+    segment_ids.assign(segments, segments + seq_len);
+    bert_encoding_free(encoding);
   }
 };
 #endif
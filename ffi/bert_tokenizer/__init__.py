@@ -1,3 +1,3 @@
 # -*- coding:utf-8 -*-
 
-from .tokenizer import FullTokenizer
+from ._native import FullTokenizer, TokenizerError
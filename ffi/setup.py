@@ -1,18 +1,28 @@
 # -*- coding:utf-8 -*-
 import setuptools
+from setuptools_rust import Binding, RustExtension
 
 setuptools.setup(
     name="bert_tokenizer",
-    version="0.1.4",
+    version="0.2.0",
     author="Li Fang",
     author_email="golifang1234@gmail.com",
     description="A Tokenizer for Bert model",
     packages=setuptools.find_packages(),
-    package_data={'':["*.py","*.so","*.dylib","*.dll"]},
+    rust_extensions=[
+        RustExtension(
+            "bert_tokenizer._native",
+            path="../Cargo.toml",
+            features=["python"],
+            binding=Binding.PyO3,
+        )
+    ],
+    install_requires=["numpy"],
+    setup_requires=["setuptools-rust"],
+    zip_safe=False,
     classifiers=[
         "Programming Language :: Python :: 3",
-        "Programming Language :: Python :: 2",
         "License :: OSI Approved :: MIT License",
         "Operating System :: OS Independent",
     ],
-)
\ No newline at end of file
+)
@@ -1,6 +1,16 @@
 # -*- coding:utf-8 -*-
-from bert_tokenizer import FullTokenizer
+import pickle
+
+from bert_tokenizer import FullTokenizer, TokenizerError
 
 tokenizer = FullTokenizer("vocab")
-print(tokenizer.convert_pairs("你好",u"UNwant\u00E9d,running",20))
-print(tokenizer.convert_pairs(u"你好",u"UNwant\u00E9d,running",20))
\ No newline at end of file
+print(tokenizer.encode(u"你好", u"UNwantéd,running", max_seq_len=20))
+print(tokenizer.encode_batch([u"你好", u"UNwantéd,running"], max_seq_len=20))
+
+tokenizer = pickle.loads(pickle.dumps(tokenizer))
+print(tokenizer.tokenize(u"UNwantéd,running"))
+
+try:
+    tokenizer.encode(u"你好", max_seq_len=1)
+except TokenizerError as e:
+    print(e)
@@ -0,0 +1,317 @@
+use std::error::Error;
+
+use padding::PaddingParams;
+use tokenization::{BasicTokenizer, FullTokenizer, InvVocab, Vocab, WordpieceTokenizer};
+use truncation::TruncationStrategy;
+
+/// Names of the special tokens of a vocabulary.
+///
+/// The defaults are the names used by the original BERT vocabularies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecialTokens {
+    pub unk_token: String,
+    pub cls_token: String,
+    pub sep_token: String,
+    /// Used for padding; padding falls back to id 0 when unset.
+    pub pad_token: Option<String>,
+    pub mask_token: Option<String>,
+    /// Extra special tokens, e.g. entity markers taken from `[unusedN]`.
+    pub additional_special_tokens: Vec<String>,
+}
+
+impl Default for SpecialTokens {
+    fn default() -> SpecialTokens {
+        SpecialTokens {
+            unk_token: "[UNK]".to_string(),
+            cls_token: "[CLS]".to_string(),
+            sep_token: "[SEP]".to_string(),
+            pad_token: Some("[PAD]".to_string()),
+            mask_token: Some("[MASK]".to_string()),
+            additional_special_tokens: Vec::new(),
+        }
+    }
+}
+
+impl SpecialTokens {
+    /// Every configured special token, paired with the name of its role.
+    pub fn with_roles(&self) -> Vec<(&'static str, &str)> {
+        let mut tokens = vec![
+            ("unk_token", self.unk_token.as_str()),
+            ("cls_token", self.cls_token.as_str()),
+            ("sep_token", self.sep_token.as_str()),
+        ];
+        if let Some(ref pad_token) = self.pad_token {
+            tokens.push(("pad_token", pad_token));
+        }
+        if let Some(ref mask_token) = self.mask_token {
+            tokens.push(("mask_token", mask_token));
+        }
+        for token in &self.additional_special_tokens {
+            tokens.push(("additional_special_tokens", token));
+        }
+        tokens
+    }
+}
+
+/// Configures and builds a `FullTokenizer`.
+///
+/// ```no_run
+/// # use bert_tokenizer::{FullTokenizerBuilder, SpecialTokens};
+/// let special_tokens = SpecialTokens {
+///     unk_token: "<unk>".to_string(),
+///     ..SpecialTokens::default()
+/// };
+/// let tokenizer = FullTokenizerBuilder::new("vocab.txt")
+///     .do_lower_case(true)
+///     .strip_accents(false)
+///     .special_tokens(special_tokens)
+///     .build()
+///     .unwrap();
+/// ```
+pub struct FullTokenizerBuilder {
+    /// The vocabulary, or the error of loading it, returned by `build`.
+    vocab: Result<(Vocab, InvVocab), Box<dyn Error>>,
+    do_lower_case: bool,
+    strip_accents: Option<bool>,
+    tokenize_chinese_chars: bool,
+    max_input_chars_per_word: usize,
+    continuing_subword_prefix: String,
+    special_tokens: SpecialTokens,
+    optional_default_tokens: bool,
+    truncation_strategy: TruncationStrategy,
+    padding: PaddingParams,
+    added_tokens: Vec<String>,
+    added_vocab: Vocab,
+    model_max_length: Option<usize>,
+}
+
+impl FullTokenizerBuilder {
+    /// Starts from the newline-separated vocabulary `vocab_file`. A file
+    /// that cannot be read is reported by [`build`](#method.build).
+    pub fn new<T: AsRef<str>>(vocab_file: T) -> FullTokenizerBuilder {
+        match FullTokenizer::load_vocab(vocab_file) {
+            Ok((vocab, inv_vocab)) => FullTokenizerBuilder::from_vocabs(vocab, inv_vocab),
+            Err(e) => FullTokenizerBuilder {
+                vocab: Err(e),
+                ..FullTokenizerBuilder::from_vocabs(Vocab::new(), InvVocab::new())
+            },
+        }
+    }
+
+    /// Starts from an in-memory vocabulary mapping tokens to ids.
+    pub fn from_vocab(vocab: Vocab) -> FullTokenizerBuilder {
+        let inv_vocab = vocab
+            .iter()
+            .map(|(token, &id)| (id, token.clone()))
+            .collect();
+        FullTokenizerBuilder::from_vocabs(vocab, inv_vocab)
+    }
+
+    pub(crate) fn from_vocabs(vocab: Vocab, inv_vocab: InvVocab) -> FullTokenizerBuilder {
+        FullTokenizerBuilder {
+            vocab: Ok((vocab, inv_vocab)),
+            do_lower_case: true,
+            strip_accents: None,
+            tokenize_chinese_chars: true,
+            max_input_chars_per_word: 100,
+            continuing_subword_prefix: "##".to_string(),
+            special_tokens: SpecialTokens::default(),
+            optional_default_tokens: false,
+            truncation_strategy: TruncationStrategy::default(),
+            padding: PaddingParams::default(),
+            added_tokens: Vec::new(),
+            added_vocab: Vocab::new(),
+            model_max_length: None,
+        }
+    }
+
+    pub fn do_lower_case(mut self, do_lower_case: bool) -> FullTokenizerBuilder {
+        self.do_lower_case = do_lower_case;
+        self
+    }
+
+    /// Strips accents independently of lowercasing. By default accents are
+    /// stripped exactly when lowercasing.
+    pub fn strip_accents(mut self, strip_accents: bool) -> FullTokenizerBuilder {
+        self.strip_accents = Some(strip_accents);
+        self
+    }
+
+    /// Surrounds CJK ideographs with whitespace so every one becomes its own
+    /// word. On by default.
+    pub fn tokenize_chinese_chars(mut self, tokenize_chinese_chars: bool) -> FullTokenizerBuilder {
+        self.tokenize_chinese_chars = tokenize_chinese_chars;
+        self
+    }
+
+    /// Words longer than this many chars become the unknown token. 100 by
+    /// default.
+    pub fn max_input_chars_per_word(
+        mut self,
+        max_input_chars_per_word: usize,
+    ) -> FullTokenizerBuilder {
+        self.max_input_chars_per_word = max_input_chars_per_word;
+        self
+    }
+
+    /// Prefix marking word pieces that continue a word, `##` by default.
+    pub fn continuing_subword_prefix<T: AsRef<str>>(mut self, prefix: T) -> FullTokenizerBuilder {
+        self.continuing_subword_prefix = prefix.as_ref().to_string();
+        self
+    }
+
+    pub fn special_tokens(mut self, special_tokens: SpecialTokens) -> FullTokenizerBuilder {
+        self.special_tokens = special_tokens;
+        self
+    }
+
+    /// Leaves out the default `[PAD]` and `[MASK]` tokens when the
+    /// vocabulary lacks them instead of failing, as `FullTokenizer::new`
+    /// does. Tokens with other names are still required. Off by default.
+    pub fn optional_default_tokens(mut self, optional: bool) -> FullTokenizerBuilder {
+        self.optional_default_tokens = optional;
+        self
+    }
+
+    pub fn truncation_strategy(mut self, strategy: TruncationStrategy) -> FullTokenizerBuilder {
+        self.truncation_strategy = strategy;
+        self
+    }
+
+    pub fn padding(mut self, padding: PaddingParams) -> FullTokenizerBuilder {
+        self.padding = padding;
+        self
+    }
+
+    /// Tokens of the vocabulary that are matched verbatim in the input and
+    /// never split, like the added tokens of Hugging Face tokenizers.
+    /// Special tokens are not matched unless they are added here too.
+    pub fn added_tokens(mut self, added_tokens: Vec<String>) -> FullTokenizerBuilder {
+        self.added_tokens = added_tokens;
+        self
+    }
+
+    /// Added tokens with ids outside the WordPiece vocabulary, as loaded
+    /// from Hugging Face files. They are matched verbatim like
+    /// `added_tokens` and never produced by WordPiece.
+    pub(crate) fn added_vocab(mut self, added_vocab: Vocab) -> FullTokenizerBuilder {
+        self.added_vocab = added_vocab;
+        self
+    }
+
+    /// Longest sequence the model accepts, to which a `max_seq_len` of 0
+    /// truncates. Unset by default.
+    pub fn model_max_length(mut self, model_max_length: usize) -> FullTokenizerBuilder {
+        self.model_max_length = Some(model_max_length);
+        self
+    }
+
+    /// Checks that every configured special token is part of the
+    /// vocabulary.
+    pub fn build(self) -> Result<FullTokenizer, Box<dyn Error>> {
+        let (vocab, inv_vocab) = self.vocab?;
+
+        let added_vocab = self.added_vocab;
+        for (token, id) in &added_vocab {
+            if vocab.contains_key(token) || inv_vocab.contains_key(id) {
+                return Err(format!("added token `{}` ({}) overlaps the vocab", token, id).into());
+            }
+        }
+        let id_of = |token: &str| vocab.get(token).or_else(|| added_vocab.get(token)).cloned();
+
+        let mut special_tokens = self.special_tokens;
+        let mut tokens = self.added_tokens;
+        if self.optional_default_tokens {
+            let defaults = SpecialTokens::default();
+            for (token, default) in [
+                (&mut special_tokens.pad_token, defaults.pad_token),
+                (&mut special_tokens.mask_token, defaults.mask_token),
+            ] {
+                if *token == default && id_of(default.as_ref().unwrap()).is_none() {
+                    tokens.retain(|added| Some(added) != default.as_ref());
+                    *token = None;
+                }
+            }
+        }
+        for (role, token) in special_tokens.with_roles() {
+            if id_of(token).is_none() {
+                return Err(format!("special token `{}` ({}) not in vocab", token, role).into());
+            }
+        }
+        if !vocab.contains_key(&special_tokens.unk_token) {
+            return Err(format!(
+                "unknown token `{}` must be part of the WordPiece vocab",
+                special_tokens.unk_token
+            )
+            .into());
+        }
+
+        let cls_token_id = id_of(&special_tokens.cls_token).unwrap();
+        let sep_token_id = id_of(&special_tokens.sep_token).unwrap();
+        let pad_token_id = special_tokens
+            .pad_token
+            .as_ref()
+            .and_then(|token| id_of(token));
+        let mask_token_id = special_tokens
+            .mask_token
+            .as_ref()
+            .and_then(|token| id_of(token));
+        let mut special_token_ids = vec![cls_token_id, sep_token_id];
+        special_token_ids.extend(pad_token_id);
+        for token in &special_tokens.additional_special_tokens {
+            special_token_ids.extend(id_of(token));
+        }
+
+        for token in added_vocab.keys() {
+            if !tokens.contains(token) {
+                tokens.push(token.clone());
+            }
+        }
+        let mut added_tokens = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            match id_of(&token) {
+                _ if token.is_empty() => return Err("added tokens must not be empty".into()),
+                Some(id) => added_tokens.push((token, id)),
+                None => return Err(format!("added token `{}` not in vocab", token).into()),
+            }
+        }
+        added_tokens.sort_by_key(|(token, _)| std::cmp::Reverse(token.len()));
+
+        if self.continuing_subword_prefix.is_empty() {
+            return Err("continuing subword prefix must not be empty".into());
+        }
+
+        let basic_tokenizer = BasicTokenizer {
+            do_lower_case: self.do_lower_case,
+            strip_accents: self.strip_accents.unwrap_or(self.do_lower_case),
+            tokenize_chinese_chars: self.tokenize_chinese_chars,
+        };
+        let wordpiece_tokenizer = WordpieceTokenizer::new(
+            vocab,
+            inv_vocab,
+            &special_tokens.unk_token,
+            self.max_input_chars_per_word,
+            &self.continuing_subword_prefix,
+        );
+
+        Ok(FullTokenizer {
+            basic_tokenizer,
+            wordpiece_tokenizer,
+            special_tokens,
+            cls_token_id,
+            sep_token_id,
+            pad_token_id,
+            mask_token_id,
+            special_token_ids,
+            truncation_strategy: self.truncation_strategy,
+            padding: self.padding,
+            added_tokens,
+            inv_added_vocab: added_vocab
+                .iter()
+                .map(|(token, &id)| (id, token.clone()))
+                .collect(),
+            added_vocab,
+            model_max_length: self.model_max_length,
+        })
+    }
+}
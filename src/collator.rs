@@ -0,0 +1,412 @@
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashSet;
+use std::error::Error;
+
+use encoding::Encoding;
+use tokenization::FullTokenizer;
+
+/// Label of the positions that are not predicted, ignored by the loss.
+pub const IGNORE_INDEX: i64 = -100;
+
+/// Masked input ids of a batch, with the ids to predict.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MlmBatch {
+    pub input_ids: Vec<Vec<i64>>,
+    /// The original id at every masked position, `IGNORE_INDEX` elsewhere.
+    pub labels: Vec<Vec<i64>>,
+}
+
+/// Masks batches of encodings on the fly for masked language modeling,
+/// drawing new masks on every call so that each epoch sees different ones.
+///
+/// Special tokens and padding are never masked. Every sequence gets a budget
+/// of `mlm_probability` of its other tokens, at least one; the chosen tokens
+/// are replaced by the mask token 80% of the time, by a random token that is
+/// not special 10% of the time and kept otherwise.
+///
+/// ```no_run
+/// # use bert_tokenizer::{FullTokenizer, MlmCollator};
+/// let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+/// let mut collator = MlmCollator::new(&tokenizer)
+///     .unwrap()
+///     .span_masking(0.2, 10)
+///     .seed(42);
+/// let encodings = tokenizer.encode_batch(&["今天天气很好"], 128).unwrap();
+/// let batch = collator.collate(&encodings).unwrap();
+/// ```
+pub struct MlmCollator {
+    mask_token_id: i64,
+    special_ids: HashSet<i64>,
+    random_ids: Vec<i64>,
+    mlm_probability: f64,
+    max_predictions_per_seq: Option<usize>,
+    mask_replace_prob: f64,
+    random_replace_prob: f64,
+    /// Success probability of the geometric span length, and the longest
+    /// span.
+    span_masking: Option<(f64, usize)>,
+    rng: ChaCha8Rng,
+}
+
+impl MlmCollator {
+    /// Fails if the tokenizer has no mask token.
+    pub fn new(tokenizer: &FullTokenizer) -> Result<MlmCollator, Box<dyn Error>> {
+        let mask_token_id = match tokenizer.mask_token_id() {
+            Some(id) => id as i64,
+            None => return Err("the tokenizer has no mask token".into()),
+        };
+        let special_ids: HashSet<i64> = tokenizer
+            .special_tokens()
+            .with_roles()
+            .iter()
+            .filter_map(|&(_, token)| tokenizer.token_to_id(token))
+            .map(|id| id as i64)
+            .collect();
+        let random_ids: Vec<i64> = tokenizer
+            .vocab()
+            .map(|(_, id)| id as i64)
+            .filter(|id| !special_ids.contains(id))
+            .collect();
+        if random_ids.is_empty() {
+            return Err("the vocab has no tokens besides the special tokens".into());
+        }
+        Ok(MlmCollator {
+            mask_token_id,
+            special_ids,
+            random_ids,
+            mlm_probability: 0.15,
+            max_predictions_per_seq: None,
+            mask_replace_prob: 0.8,
+            random_replace_prob: 0.1,
+            span_masking: None,
+            rng: ChaCha8Rng::from_entropy(),
+        })
+    }
+
+    /// Fraction of the tokens of every sequence to predict. 0.15 by default.
+    pub fn mlm_probability(mut self, mlm_probability: f64) -> MlmCollator {
+        self.mlm_probability = mlm_probability;
+        self
+    }
+
+    /// Caps the number of tokens predicted per sequence. Unlimited by
+    /// default.
+    pub fn max_predictions_per_seq(mut self, max_predictions_per_seq: usize) -> MlmCollator {
+        self.max_predictions_per_seq = Some(max_predictions_per_seq);
+        self
+    }
+
+    /// Share of the chosen tokens replaced by the mask token. 0.8 by
+    /// default.
+    pub fn mask_replace_prob(mut self, mask_replace_prob: f64) -> MlmCollator {
+        self.mask_replace_prob = mask_replace_prob;
+        self
+    }
+
+    /// Share of the chosen tokens replaced by a random token. 0.1 by
+    /// default; the rest keeps the original token.
+    pub fn random_replace_prob(mut self, random_replace_prob: f64) -> MlmCollator {
+        self.random_replace_prob = random_replace_prob;
+        self
+    }
+
+    /// Chooses spans of consecutive tokens instead of single tokens, as
+    /// SpanBERT. Span lengths follow a geometric distribution with success
+    /// probability `p`, clipped to `max_span_len`; SpanBERT uses 0.2 and 10.
+    pub fn span_masking(mut self, p: f64, max_span_len: usize) -> MlmCollator {
+        self.span_masking = Some((p, max_span_len.max(1)));
+        self
+    }
+
+    /// Makes the masks reproducible. Seeded from the OS by default.
+    pub fn seed(mut self, seed: u64) -> MlmCollator {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+        self
+    }
+
+    /// Fails on probabilities outside [0, 1], replacement shares adding up to
+    /// more than 1 and span probabilities of 0.
+    fn check(&self) -> Result<(), Box<dyn Error>> {
+        let probabilities = [
+            ("mlm_probability", self.mlm_probability),
+            ("mask_replace_prob", self.mask_replace_prob),
+            ("random_replace_prob", self.random_replace_prob),
+        ];
+        for &(name, p) in &probabilities {
+            if !(0.0..=1.0).contains(&p) {
+                return Err(format!("{} {} is not in [0, 1]", name, p).into());
+            }
+        }
+        if self.mask_replace_prob + self.random_replace_prob > 1.0 {
+            return Err(format!(
+                "mask_replace_prob {} and random_replace_prob {} add up to more than 1",
+                self.mask_replace_prob, self.random_replace_prob
+            )
+            .into());
+        }
+        if let Some((p, _)) = self.span_masking {
+            if !(p > 0.0 && p <= 1.0) {
+                return Err(format!("span masking probability {} is not in (0, 1]", p).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws a span length from the geometric distribution, at least 1.
+    fn span_len(&mut self, p: f64, max_span_len: usize) -> usize {
+        if p >= 1.0 {
+            return 1;
+        }
+        // inverse transform sampling, with `u` in (0, 1]
+        let u = 1.0 - self.rng.gen::<f64>();
+        let len = 1.0 + (u.ln() / (1.0 - p).ln()).floor();
+        (len as usize).min(max_span_len)
+    }
+
+    /// Positions to predict in one sequence, in increasing order.
+    fn choose(&mut self, candidates: &[bool]) -> Vec<usize> {
+        let positions: Vec<usize> = (0..candidates.len()).filter(|&i| candidates[i]).collect();
+        if positions.is_empty() {
+            return positions;
+        }
+        let mut budget = ((positions.len() as f64 * self.mlm_probability).round() as usize)
+            .max(1)
+            .min(positions.len());
+        if let Some(max_predictions_per_seq) = self.max_predictions_per_seq {
+            budget = budget.min(max_predictions_per_seq);
+        }
+
+        let mut chosen = match self.span_masking {
+            None => {
+                let mut positions = positions;
+                positions.shuffle(&mut self.rng);
+                positions.truncate(budget);
+                positions
+            }
+            Some((p, max_span_len)) => {
+                let mut is_chosen = vec![false; candidates.len()];
+                let mut chosen = Vec::with_capacity(budget);
+                while chosen.len() < budget {
+                    let span_len = self.span_len(p, max_span_len).min(budget - chosen.len());
+                    let free: Vec<usize> = positions
+                        .iter()
+                        .cloned()
+                        .filter(|&i| !is_chosen[i])
+                        .collect();
+                    let start = free[self.rng.gen_range(0..free.len())];
+                    // spans stop at special tokens and chosen tokens
+                    for i in start..candidates.len().min(start + span_len) {
+                        if !candidates[i] || is_chosen[i] {
+                            break;
+                        }
+                        is_chosen[i] = true;
+                        chosen.push(i);
+                    }
+                }
+                chosen
+            }
+        };
+        chosen.sort_unstable();
+        chosen
+    }
+
+    /// Masks every encoding of `encodings`, which are left untouched. Fails
+    /// if the probabilities set are invalid.
+    pub fn collate(&mut self, encodings: &[Encoding]) -> Result<MlmBatch, Box<dyn Error>> {
+        self.check()?;
+        let mut batch = MlmBatch {
+            input_ids: Vec::with_capacity(encodings.len()),
+            labels: Vec::with_capacity(encodings.len()),
+        };
+        for encoding in encodings {
+            let mut input_ids = encoding.input_ids.clone();
+            let mut labels = vec![IGNORE_INDEX; input_ids.len()];
+            let candidates: Vec<bool> = (0..input_ids.len())
+                .map(|i| {
+                    encoding.special_tokens_mask[i] == 0
+                        && !self.special_ids.contains(&input_ids[i])
+                })
+                .collect();
+            for position in self.choose(&candidates) {
+                labels[position] = input_ids[position];
+                let r = self.rng.gen::<f64>();
+                if r < self.mask_replace_prob {
+                    input_ids[position] = self.mask_token_id;
+                } else if r < self.mask_replace_prob + self.random_replace_prob {
+                    input_ids[position] =
+                        self.random_ids[self.rng.gen_range(0..self.random_ids.len())];
+                }
+            }
+            batch.input_ids.push(input_ids);
+            batch.labels.push(labels);
+        }
+        Ok(batch)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use padding::{PaddingParams, PaddingStrategy};
+
+    fn encodings(tokenizer: &mut FullTokenizer) -> Vec<Encoding> {
+        tokenizer.set_padding(PaddingParams {
+            strategy: PaddingStrategy::BatchLongest,
+            ..PaddingParams::default()
+        });
+        let texts = [
+            "今天天气很好，我们一起去公园散步吧。",
+            "hello world",
+            "北京是中华人民共和国的首都，也是全国的政治文化中心。",
+        ];
+        tokenizer.encode_batch(&texts, 0).unwrap()
+    }
+
+    #[test]
+    fn test_collate() {
+        let mut tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let encodings = encodings(&mut tokenizer);
+        let mut collator = MlmCollator::new(&tokenizer).unwrap().seed(7);
+
+        let batch = collator.collate(&encodings).unwrap();
+        assert_ne!(batch, collator.collate(&encodings).unwrap());
+        let mut reseeded = MlmCollator::new(&tokenizer).unwrap().seed(7);
+        assert_eq!(batch, reseeded.collate(&encodings).unwrap());
+
+        for _ in 0..20 {
+            let batch = collator.collate(&encodings).unwrap();
+            for (i, encoding) in encodings.iter().enumerate() {
+                let (input_ids, labels) = (&batch.input_ids[i], &batch.labels[i]);
+                let num_tokens = encoding
+                    .special_tokens_mask
+                    .iter()
+                    .filter(|&&s| s == 0)
+                    .count();
+                let budget = ((num_tokens as f64 * 0.15).round() as usize).max(1);
+                assert_eq!(
+                    labels.iter().filter(|&&l| l != IGNORE_INDEX).count(),
+                    budget
+                );
+                for j in 0..input_ids.len() {
+                    if labels[j] == IGNORE_INDEX {
+                        assert_eq!(input_ids[j], encoding.input_ids[j]);
+                    } else {
+                        assert_eq!(encoding.special_tokens_mask[j], 0);
+                        assert_eq!(labels[j], encoding.input_ids[j]);
+                    }
+                }
+            }
+        }
+
+        let mut collator = MlmCollator::new(&tokenizer)
+            .unwrap()
+            .mlm_probability(1.0)
+            .max_predictions_per_seq(4)
+            .mask_replace_prob(0.0)
+            .random_replace_prob(1.0);
+        let special_ids = [0, 100, 101, 102, 103];
+        for _ in 0..20 {
+            let batch = collator.collate(&encodings).unwrap();
+            for (i, encoding) in encodings.iter().enumerate() {
+                let (input_ids, labels) = (&batch.input_ids[i], &batch.labels[i]);
+                let num_tokens = encoding
+                    .special_tokens_mask
+                    .iter()
+                    .filter(|&&s| s == 0)
+                    .count();
+                assert_eq!(
+                    labels.iter().filter(|&&l| l != IGNORE_INDEX).count(),
+                    num_tokens.min(4)
+                );
+                for (id, &label) in input_ids.iter().zip(labels) {
+                    if label != IGNORE_INDEX {
+                        assert!(!special_ids.contains(id));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_span_masking() {
+        let mut tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let encodings = encodings(&mut tokenizer);
+        let mut collator = MlmCollator::new(&tokenizer)
+            .unwrap()
+            .mlm_probability(0.3)
+            .span_masking(0.2, 4)
+            .seed(3);
+        let (mut num_masked, mut num_spans) = (0, 0);
+        for _ in 0..50 {
+            let batch = collator.collate(&encodings).unwrap();
+            for (encoding, labels) in encodings.iter().zip(&batch.labels) {
+                let num_tokens = encoding
+                    .special_tokens_mask
+                    .iter()
+                    .filter(|&&s| s == 0)
+                    .count();
+                let budget = ((num_tokens as f64 * 0.3).round() as usize).max(1);
+                let masked: Vec<usize> = (0..labels.len())
+                    .filter(|&j| labels[j] != IGNORE_INDEX)
+                    .collect();
+                assert_eq!(masked.len(), budget);
+                assert!(masked.iter().all(|&j| encoding.special_tokens_mask[j] == 0));
+                num_masked += masked.len();
+                num_spans += 1 + masked
+                    .windows(2)
+                    .filter(|pair| pair[1] != pair[0] + 1)
+                    .count();
+            }
+        }
+        // geometric lengths with p = 0.2 clipped to 4 average about 2.4,
+        // adjacent spans merge into longer runs
+        assert!(num_masked as f64 / num_spans as f64 > 2.0);
+
+        // every token, in spans
+        let mut collator = MlmCollator::new(&tokenizer)
+            .unwrap()
+            .mlm_probability(1.0)
+            .span_masking(0.2, 10);
+        let batch = collator.collate(&encodings).unwrap();
+        for (encoding, labels) in encodings.iter().zip(&batch.labels) {
+            for (&s, &label) in encoding.special_tokens_mask.iter().zip(labels) {
+                assert_eq!(s == 0, label != IGNORE_INDEX);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_probabilities() {
+        let mut tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let encodings = encodings(&mut tokenizer);
+        let collator = || MlmCollator::new(&tokenizer).unwrap();
+        let errors = vec![
+            (
+                collator().mlm_probability(1.5),
+                "mlm_probability 1.5 is not in [0, 1]",
+            ),
+            (
+                collator().mlm_probability(f64::NAN),
+                "mlm_probability NaN is not in [0, 1]",
+            ),
+            (
+                collator().random_replace_prob(-0.1),
+                "random_replace_prob -0.1 is not in [0, 1]",
+            ),
+            (
+                collator().mask_replace_prob(0.5).random_replace_prob(0.6),
+                "mask_replace_prob 0.5 and random_replace_prob 0.6 add up to more than 1",
+            ),
+            (
+                collator().span_masking(0.0, 10),
+                "span masking probability 0 is not in (0, 1]",
+            ),
+        ];
+        for (mut collator, message) in errors.into_iter() {
+            let error = collator.collate(&encodings).err().unwrap();
+            assert_eq!(error.to_string(), message);
+        }
+    }
+}
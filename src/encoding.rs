@@ -0,0 +1,152 @@
+use padding::{PaddingDirection, PaddingParams};
+use std::error::Error;
+use std::iter;
+use tokenization::Piece;
+
+/// The model inputs produced by encoding a sequence or a sequence pair.
+///
+/// All vectors have the same length, one entry per position.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Encoding {
+    /// Vocabulary ids of every position.
+    pub input_ids: Vec<i64>,
+    /// Attention mask: 1 for real tokens, 0 for padding.
+    pub input_mask: Vec<i64>,
+    /// Token type ids: 0 for the first sequence, 1 for the second.
+    pub segment_ids: Vec<i64>,
+    /// Token strings of every position.
+    pub tokens: Vec<String>,
+    /// 1 for `[CLS]`, `[SEP]` and padding, 0 for tokens from the input.
+    pub special_tokens_mask: Vec<i64>,
+    /// `(start, end)` char offsets of every token into the text it came
+    /// from; `(0, 0)` for special tokens and padding.
+    pub offsets: Vec<(usize, usize)>,
+    /// Same as `offsets`, in UTF-8 bytes.
+    pub byte_offsets: Vec<(usize, usize)>,
+    /// Index of the word every token came from within its sequence; `None`
+    /// for special tokens and padding.
+    pub word_ids: Vec<Option<usize>>,
+    /// Number of tokens truncation dropped from the first sequence.
+    pub num_truncated_a: usize,
+    /// Number of tokens truncation dropped from the second sequence.
+    pub num_truncated_b: usize,
+    /// For a window over a long second sequence, the index of its first
+    /// token within that sequence's tokens.
+    pub window_offset: usize,
+    /// For a window over a long second sequence, the index of the input it
+    /// was produced from.
+    pub overflow_to_sample: usize,
+}
+
+impl Encoding {
+    pub fn with_capacity(capacity: usize) -> Encoding {
+        Encoding {
+            input_ids: Vec::with_capacity(capacity),
+            input_mask: Vec::with_capacity(capacity),
+            segment_ids: Vec::with_capacity(capacity),
+            tokens: Vec::with_capacity(capacity),
+            special_tokens_mask: Vec::with_capacity(capacity),
+            offsets: Vec::with_capacity(capacity),
+            byte_offsets: Vec::with_capacity(capacity),
+            word_ids: Vec::with_capacity(capacity),
+            num_truncated_a: 0,
+            num_truncated_b: 0,
+            window_offset: 0,
+            overflow_to_sample: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.input_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.input_ids.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, piece: &Piece, token: &str, segment_id: i64) {
+        self.input_ids.push(piece.id);
+        self.tokens.push(token.to_string());
+        self.input_mask.push(1);
+        self.segment_ids.push(segment_id);
+        self.special_tokens_mask.push(0);
+        self.offsets.push(piece.offsets);
+        self.byte_offsets.push(piece.byte_offsets);
+        self.word_ids.push(Some(piece.word));
+    }
+
+    pub(crate) fn push_special(&mut self, id: i64, token: &str, segment_id: i64) {
+        self.input_ids.push(id);
+        self.tokens.push(token.to_string());
+        self.input_mask.push(1);
+        self.segment_ids.push(segment_id);
+        self.special_tokens_mask.push(1);
+        self.offsets.push((0, 0));
+        self.byte_offsets.push((0, 0));
+        self.word_ids.push(None);
+    }
+
+    /// Pads up to `target_len` on the side and with the values of `params`,
+    /// using `pad_id` as input id.
+    pub(crate) fn pad(
+        &mut self,
+        target_len: usize,
+        params: &PaddingParams,
+        pad_id: i64,
+        pad_token: &str,
+    ) {
+        if self.len() >= target_len {
+            return;
+        }
+        let n = target_len - self.len();
+        fn extend<T: Clone>(values: &mut Vec<T>, value: T, n: usize, left: bool) {
+            if left {
+                values.splice(0..0, iter::repeat_n(value, n));
+            } else {
+                values.extend(iter::repeat_n(value, n));
+            }
+        }
+        let left = params.direction == PaddingDirection::Left;
+        extend(&mut self.input_ids, pad_id, n, left);
+        extend(&mut self.tokens, pad_token.to_string(), n, left);
+        extend(&mut self.input_mask, params.pad_mask_value, n, left);
+        extend(&mut self.segment_ids, params.pad_type_id, n, left);
+        extend(&mut self.special_tokens_mask, 1, n, left);
+        extend(&mut self.offsets, (0, 0), n, left);
+        extend(&mut self.byte_offsets, (0, 0), n, left);
+        extend(&mut self.word_ids, None, n, left);
+    }
+
+    /// Spreads word-level `labels` of the first sequence onto its tokens.
+    ///
+    /// Only the first token of every word gets the label of its word unless
+    /// `label_all_subtokens` is set. All other positions, including special
+    /// tokens, padding and the second sequence, get `ignore_index`, e.g.
+    /// -100 for PyTorch's cross entropy.
+    pub fn align_labels(
+        &self,
+        labels: &[i64],
+        label_all_subtokens: bool,
+        ignore_index: i64,
+    ) -> Result<Vec<i64>, Box<dyn Error + Send + Sync>> {
+        let mut aligned = Vec::with_capacity(self.len());
+        let mut previous = None;
+        for (i, &word) in self.word_ids.iter().enumerate() {
+            let label = match word {
+                Some(word) if self.segment_ids[i] == 0 => {
+                    if !label_all_subtokens && previous == Some(word) {
+                        ignore_index
+                    } else {
+                        *labels.get(word).ok_or_else(|| {
+                            format!("no label for word {} of {} labels", word, labels.len())
+                        })?
+                    }
+                }
+                _ => ignore_index,
+            };
+            aligned.push(label);
+            previous = word;
+        }
+        Ok(aligned)
+    }
+}
@@ -1,136 +1,846 @@
-use super::{FullTokenizer, ERROR_MSG, INPUT_IDS, INPUT_MASK, SEGMENT_IDS};
+// The exported functions are called from C, where an `unsafe` qualifier on the
+// Rust side carries no meaning; pointer validity is the caller's contract.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use super::{Encoding, FullTokenizer, ERROR_CODE, ERROR_MSG, LAST_ENCODING};
+use rayon::prelude::*;
+use std::any::Any;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::error::Error;
 use std::ffi::{CStr, CString};
-use std::mem;
 use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr;
 
+/// Kind of the last error of the calling thread, next to the message of
+/// `bert_tokenizer_get_error`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidArgument = 2,
+    BufferTooSmall = 3,
+    EncodeFailed = 4,
+    LoadFailed = 5,
+    /// A bug in the tokenizer; the panic was caught at the ffi boundary.
+    Panic = 6,
+}
+
+struct FfiError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl FfiError {
+    fn new<E: ToString>(code: ErrorCode, message: E) -> FfiError {
+        FfiError {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    fn encode(e: Box<dyn Error + Send + Sync>) -> FfiError {
+        FfiError::new(ErrorCode::EncodeFailed, e)
+    }
+}
+
 #[no_mangle]
-pub fn bert_tokenizer_get_error() -> *const c_char {
-    let error_ptr = ERROR_MSG.with(|error_msg| {
-        return error_msg.borrow().as_ptr();
-    });
-    error_ptr
+pub extern "C" fn bert_tokenizer_get_error() -> *const c_char {
+    ERROR_MSG.with(|error_msg| error_msg.borrow().as_ptr())
 }
 
+/// Code of the last failed call of this thread, `Ok` after a successful one.
 #[no_mangle]
-pub fn create_full_tokenizer(vocab_file: *const c_char, do_lower_case: c_int) -> *mut c_void {
-    let vocab_file = unsafe { CStr::from_ptr(vocab_file) };
-    let vocab_file = vocab_file.to_string_lossy();
-    match FullTokenizer::new(vocab_file, do_lower_case == 1) {
-        Ok(tokenizer) => {
-            let tokenizer = Box::new(tokenizer);
-            Box::into_raw(tokenizer) as *mut c_void
+pub extern "C" fn bert_tokenizer_get_error_code() -> ErrorCode {
+    ERROR_CODE.with(|error_code| error_code.get())
+}
+
+fn set_error<E: ToString>(code: ErrorCode, e: E) {
+    ERROR_CODE.with(|error_code| error_code.set(code));
+    ERROR_MSG.with(|error_msg| {
+        let mut reason = e.to_string().into_bytes();
+        reason.retain(|&b| b != 0);
+        *error_msg.borrow_mut() = CString::new(reason).unwrap_or_default();
+    });
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        format!("tokenizer panicked: {}", message)
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        format!("tokenizer panicked: {}", message)
+    } else {
+        "tokenizer panicked".to_string()
+    }
+}
+
+/// Runs the body of an exported function, turning errors and panics into
+/// `on_error` and the error state of the calling thread.
+fn guard<T, F>(on_error: T, f: F) -> T
+where
+    F: FnOnce() -> Result<T, FfiError>,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => {
+            ERROR_CODE.with(|error_code| error_code.set(ErrorCode::Ok));
+            value
         }
-        Err(e) => {
-            ERROR_MSG.with(|error_msg| {
-                let mut error_msg = error_msg.borrow_mut();
-                let reason = e.to_string().into_bytes();
-                *error_msg = CString::new(reason).unwrap();
-            });
-            ptr::null_mut()
+        Ok(Err(e)) => {
+            set_error(e.code, e.message);
+            on_error
         }
+        Err(payload) => {
+            set_error(ErrorCode::Panic, panic_message(&*payload));
+            on_error
+        }
+    }
+}
+
+fn non_null<'a, T>(ptr: *const T, name: &str) -> Result<&'a T, FfiError> {
+    if ptr.is_null() {
+        Err(FfiError::new(
+            ErrorCode::NullPointer,
+            format!("`{}` is null", name),
+        ))
+    } else {
+        Ok(unsafe { &*ptr })
+    }
+}
+
+fn tokenizer<'a>(tokenizer: *const c_void) -> Result<&'a FullTokenizer, FfiError> {
+    non_null(tokenizer as *const FullTokenizer, "tokenizer")
+}
+
+fn c_str<'a>(text: *const c_char, name: &str) -> Result<Cow<'a, str>, FfiError> {
+    non_null(text, name)?;
+    Ok(unsafe { CStr::from_ptr(text) }.to_string_lossy())
+}
+
+fn length(len: c_int, name: &str) -> Result<usize, FfiError> {
+    if len < 0 {
+        Err(FfiError::new(
+            ErrorCode::InvalidArgument,
+            format!("`{}` is negative: {}", name, len),
+        ))
+    } else {
+        Ok(len as usize)
     }
 }
 
 #[no_mangle]
-pub fn drop_tokenizer(tokenizer: *mut c_void) {
-    unsafe { Box::from_raw(tokenizer) };
+pub extern "C" fn create_full_tokenizer(
+    vocab_file: *const c_char,
+    do_lower_case: c_int,
+) -> *mut c_void {
+    guard(ptr::null_mut(), || {
+        let vocab_file = c_str(vocab_file, "vocab_file")?;
+        let tokenizer = FullTokenizer::new(vocab_file, do_lower_case == 1)
+            .map_err(|e| FfiError::new(ErrorCode::LoadFailed, e))?;
+        Ok(Box::into_raw(Box::new(tokenizer)) as *mut c_void)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn drop_tokenizer(tokenizer: *mut c_void) {
+    guard((), || {
+        if !tokenizer.is_null() {
+            drop(unsafe { Box::from_raw(tokenizer as *mut FullTokenizer) });
+        }
+        Ok(())
+    })
 }
 
 #[no_mangle]
-pub fn convert_to_ids(
+pub extern "C" fn convert_to_ids(
     tokenizer: *mut c_void,
     text: *const c_char,
     output_len: *mut c_int,
 ) -> *mut c_int {
-    let tokenizer = unsafe { &*(tokenizer as *mut FullTokenizer) };
+    guard(ptr::null_mut(), || {
+        let tokenizer = self::tokenizer(tokenizer)?;
+        let text = c_str(text, "text")?;
+        non_null(output_len, "output_len")?;
 
-    let text = unsafe { CStr::from_ptr(text) }.to_string_lossy();
+        let ids: Box<[c_int]> = tokenizer
+            .tokenize_to_ids(text)
+            .iter()
+            .map(|&id| id as c_int)
+            .collect();
+        unsafe {
+            *output_len = ids.len() as c_int;
+        }
+        Ok(Box::into_raw(ids) as *mut c_int)
+    })
+}
 
-    let mut ids: Vec<c_int> = tokenizer
-        .convert_tokens_to_ids(&tokenizer.tokenize(text))
-        .iter()
-        .map(|v| *v as _)
-        .collect();
+#[no_mangle]
+pub extern "C" fn drop_ids(ids_ptr: *mut c_int, len: c_int) {
+    guard((), || {
+        let len = length(len, "len")?;
+        if !ids_ptr.is_null() {
+            drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(ids_ptr, len)) });
+        }
+        Ok(())
+    })
+}
 
-    let ids_data = ids.as_mut_ptr();
+#[no_mangle]
+pub extern "C" fn convert_pairs(
+    tokenizer: *mut c_void,
+    text_a: *const c_char,
+    text_b: *const c_char,
+    max_seq_len: c_int,
+    is_pair: c_int,
+) -> c_int {
+    guard(-1, || {
+        let tokenizer = self::tokenizer(tokenizer)?;
+        let text_a = c_str(text_a, "text_a")?;
+        let text_b = if is_pair == 1 {
+            c_str(text_b, "text_b")?
+        } else {
+            Cow::Borrowed("")
+        };
+        let max_seq_len = length(max_seq_len, "max_seq_len")?;
+        let seq_len = tokenizer
+            .convert_pairs(text_a, text_b, max_seq_len, is_pair == 1)
+            .map_err(FfiError::encode)?;
+        Ok(seq_len as c_int)
+    })
+}
 
-    unsafe {
-        *output_len = ids.len() as _;
-    }
+// The functions above pass results through thread locals and are kept for
+// compatibility. The `bert_tokenizer_encode*` functions below return them
+// directly and are safe to call concurrently on a shared tokenizer.
 
-    mem::forget(ids);
-    ids_data
+fn encode(
+    tokenizer: *const c_void,
+    text_a: *const c_char,
+    text_b: *const c_char,
+    max_seq_len: usize,
+) -> Result<Encoding, FfiError> {
+    let tokenizer = self::tokenizer(tokenizer)?;
+    let text_a = c_str(text_a, "text_a")?;
+    let encoding = if text_b.is_null() {
+        tokenizer.encode(text_a, max_seq_len)
+    } else {
+        let text_b = c_str(text_b, "text_b")?;
+        tokenizer.encode_pair(text_a, text_b, max_seq_len)
+    };
+    encoding.map_err(FfiError::encode)
 }
 
+/// Encodes `text_a`, or the pair `text_a`, `text_b` unless `text_b` is null.
+/// Returns null on error; the encoding must be released with
+/// `bert_encoding_free`.
 #[no_mangle]
-pub fn drop_ids(ids_ptr: *mut c_int, len: c_int) {
-    let _ids = unsafe { Vec::from_raw_parts(ids_ptr, len as usize, len as usize) };
+pub extern "C" fn bert_tokenizer_encode(
+    tokenizer: *const c_void,
+    text_a: *const c_char,
+    text_b: *const c_char,
+    max_seq_len: usize,
+) -> *mut Encoding {
+    guard(ptr::null_mut(), || {
+        let encoding = encode(tokenizer, text_a, text_b, max_seq_len)?;
+        Ok(Box::into_raw(Box::new(encoding)))
+    })
 }
 
+/// Encodes like `bert_tokenizer_encode` into caller-provided buffers of
+/// `capacity` elements; null buffers are skipped. Returns the sequence
+/// length, or -1 on error or if the sequence does not fit.
 #[no_mangle]
-pub fn convert_pairs(
-    tokenizer: *mut c_void,
+pub extern "C" fn bert_tokenizer_encode_into(
+    tokenizer: *const c_void,
     text_a: *const c_char,
     text_b: *const c_char,
-    max_seq_len: c_int,
-    is_pair: c_int,
+    max_seq_len: usize,
+    input_ids: *mut i64,
+    input_mask: *mut i64,
+    segment_ids: *mut i64,
+    capacity: usize,
 ) -> c_int {
-    let tokenizer = unsafe { &*(tokenizer as *mut FullTokenizer) };
+    guard(-1, || {
+        let encoding = encode(tokenizer, text_a, text_b, max_seq_len)?;
+        if encoding.len() > capacity {
+            return Err(FfiError::new(
+                ErrorCode::BufferTooSmall,
+                format!(
+                    "sequence of {} tokens does not fit in buffers of {}",
+                    encoding.len(),
+                    capacity
+                ),
+            ));
+        }
+        for &(src, dst) in &[
+            (&encoding.input_ids, input_ids),
+            (&encoding.input_mask, input_mask),
+            (&encoding.segment_ids, segment_ids),
+        ] {
+            if !dst.is_null() {
+                unsafe { ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len()) };
+            }
+        }
+        Ok(encoding.len() as c_int)
+    })
+}
+
+/// Encodes `batch_size` texts (or pairs) on the rayon thread pool, every one
+/// padded to `max_seq_len`.
+fn encode_batch(
+    tokenizer: *const c_void,
+    texts_a: *const *const c_char,
+    texts_b: *const *const c_char,
+    batch_size: usize,
+    max_seq_len: usize,
+) -> Result<Vec<Encoding>, FfiError> {
+    let tokenizer = self::tokenizer(tokenizer)?;
+    if max_seq_len == 0 {
+        return Err(FfiError::new(
+            ErrorCode::InvalidArgument,
+            "max_seq_len must be positive for batch encoding",
+        ));
+    }
+    non_null(texts_a, "texts_a")?;
+    let texts_a = unsafe { std::slice::from_raw_parts(texts_a, batch_size) };
+    let texts_b = if texts_b.is_null() {
+        vec![ptr::null(); batch_size]
+    } else {
+        unsafe { std::slice::from_raw_parts(texts_b, batch_size) }.to_vec()
+    };
+    let mut texts: Vec<(String, Option<String>)> = Vec::with_capacity(batch_size);
+    for (&text_a, text_b) in texts_a.iter().zip(texts_b) {
+        let text_a = c_str(text_a, "texts_a[i]")?.into_owned();
+        let text_b = if text_b.is_null() {
+            None
+        } else {
+            Some(c_str(text_b, "texts_b[i]")?.into_owned())
+        };
+        texts.push((text_a, text_b));
+    }
+
+    texts
+        .par_iter()
+        .map(|(text_a, text_b)| {
+            let mut encoding = match *text_b {
+                Some(ref text_b) => tokenizer.encode_pair(text_a, text_b, max_seq_len),
+                None => tokenizer.encode(text_a, max_seq_len),
+            }
+            .map_err(FfiError::encode)?;
+            if encoding.len() > max_seq_len {
+                return Err(FfiError::new(
+                    ErrorCode::BufferTooSmall,
+                    format!(
+                        "padded sequence of {} tokens does not fit in {} tokens",
+                        encoding.len(),
+                        max_seq_len
+                    ),
+                ));
+            }
+            tokenizer.pad_encoding(&mut encoding, max_seq_len);
+            Ok(encoding)
+        })
+        .collect()
+}
 
-    let text_a = unsafe { CStr::from_ptr(text_a) }.to_string_lossy();
-    let text_b = unsafe { CStr::from_ptr(text_b) }.to_string_lossy();
+/// Converts one output of `encodings` to the element type `T` of a caller
+/// buffer, failing if a value does not fit.
+fn convert_rows<T, F>(encodings: &[Encoding], row: F) -> Result<Vec<Vec<T>>, FfiError>
+where
+    T: TryFrom<i64>,
+    F: Fn(&Encoding) -> &[i64],
+{
+    encodings
+        .iter()
+        .map(|encoding| {
+            row(encoding)
+                .iter()
+                .map(|&value| {
+                    T::try_from(value).map_err(|_| {
+                        FfiError::new(
+                            ErrorCode::InvalidArgument,
+                            format!("value {} does not fit in the buffer element type", value),
+                        )
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
 
-    let seq_len = tokenizer.convert_pairs(
-        text_a.as_ref(),
-        text_b.as_ref(),
-        max_seq_len as usize,
-        is_pair == 1,
-    );
-    seq_len as c_int
+/// Copies `rows` into the row-major `[rows.len(), row_len]` buffer `dst`,
+/// unless it is null.
+fn write_rows<T: Copy>(dst: *mut T, row_len: usize, rows: &[Vec<T>]) {
+    if dst.is_null() {
+        return;
+    }
+    let dst = unsafe { std::slice::from_raw_parts_mut(dst, rows.len() * row_len) };
+    for (dst, row) in dst.chunks_mut(row_len).zip(rows) {
+        dst[..row.len()].copy_from_slice(row);
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
+fn encode_batch_into<T: Copy + TryFrom<i64>>(
+    tokenizer: *const c_void,
+    texts_a: *const *const c_char,
+    texts_b: *const *const c_char,
+    batch_size: usize,
+    max_seq_len: usize,
+    input_ids: *mut T,
+    input_mask: *mut T,
+    segment_ids: *mut T,
+) -> c_int {
+    guard(-1, || {
+        let encodings = encode_batch(tokenizer, texts_a, texts_b, batch_size, max_seq_len)?;
+        // every output is converted before any is written, so a failure
+        // leaves all buffers untouched
+        let ids = convert_rows(&encodings, |e| &e.input_ids)?;
+        let mask = convert_rows(&encodings, |e| &e.input_mask)?;
+        let segments = convert_rows(&encodings, |e| &e.segment_ids)?;
+        write_rows(input_ids, max_seq_len, &ids);
+        write_rows(input_mask, max_seq_len, &mask);
+        write_rows(segment_ids, max_seq_len, &segments);
+        Ok(0)
+    })
+}
+
+/// Encodes `batch_size` texts in parallel into row-major
+/// `[batch_size, max_seq_len]` buffers; null buffers are skipped. Pairs are
+/// encoded where `texts_b` and its entry are not null. Returns 0, or -1 on
+/// error, in which case the buffers are left untouched.
 #[no_mangle]
-pub fn get_input_ids() -> *mut i64 {
-    let mut input_ids_ptr: *mut i64 = ptr::null_mut();
-    INPUT_IDS.with(|input_ids| {
-        input_ids_ptr = input_ids.borrow_mut().as_mut_ptr();
-    });
-    input_ids_ptr
+pub extern "C" fn bert_tokenizer_encode_batch(
+    tokenizer: *const c_void,
+    texts_a: *const *const c_char,
+    texts_b: *const *const c_char,
+    batch_size: usize,
+    max_seq_len: usize,
+    input_ids: *mut i64,
+    input_mask: *mut i64,
+    segment_ids: *mut i64,
+) -> c_int {
+    encode_batch_into(
+        tokenizer,
+        texts_a,
+        texts_b,
+        batch_size,
+        max_seq_len,
+        input_ids,
+        input_mask,
+        segment_ids,
+    )
 }
 
+/// Same as `bert_tokenizer_encode_batch`, with int32 buffers. Fails with
+/// `InvalidArgument` if a value does not fit in int32.
 #[no_mangle]
-pub fn get_input_mask() -> *mut i64 {
-    let mut input_mask_ptr: *mut i64 = ptr::null_mut();
-    INPUT_MASK.with(|input_mask| {
-        input_mask_ptr = input_mask.borrow_mut().as_mut_ptr();
-    });
-    input_mask_ptr
+pub extern "C" fn bert_tokenizer_encode_batch_i32(
+    tokenizer: *const c_void,
+    texts_a: *const *const c_char,
+    texts_b: *const *const c_char,
+    batch_size: usize,
+    max_seq_len: usize,
+    input_ids: *mut i32,
+    input_mask: *mut i32,
+    segment_ids: *mut i32,
+) -> c_int {
+    encode_batch_into(
+        tokenizer,
+        texts_a,
+        texts_b,
+        batch_size,
+        max_seq_len,
+        input_ids,
+        input_mask,
+        segment_ids,
+    )
 }
 
+/// Length of `encoding`, 0 if it is null.
 #[no_mangle]
-pub fn get_segment_ids() -> *mut i64 {
-    let mut segment_ids_ptr: *mut i64 = ptr::null_mut();
-    SEGMENT_IDS.with(|segment_ids| {
-        segment_ids_ptr = segment_ids.borrow_mut().as_mut_ptr();
-    });
-    segment_ids_ptr
+pub extern "C" fn bert_encoding_len(encoding: *const Encoding) -> usize {
+    guard(0, || Ok(non_null(encoding, "encoding")?.len()))
+}
+
+#[no_mangle]
+pub extern "C" fn bert_encoding_input_ids(encoding: *const Encoding) -> *const i64 {
+    guard(ptr::null(), || {
+        Ok(non_null(encoding, "encoding")?.input_ids.as_ptr())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn bert_encoding_input_mask(encoding: *const Encoding) -> *const i64 {
+    guard(ptr::null(), || {
+        Ok(non_null(encoding, "encoding")?.input_mask.as_ptr())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn bert_encoding_segment_ids(encoding: *const Encoding) -> *const i64 {
+    guard(ptr::null(), || {
+        Ok(non_null(encoding, "encoding")?.segment_ids.as_ptr())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn bert_encoding_free(encoding: *mut Encoding) {
+    guard((), || {
+        if !encoding.is_null() {
+            drop(unsafe { Box::from_raw(encoding) });
+        }
+        Ok(())
+    })
+}
+
+/// Copies `token` into `buf` of `buf_len` bytes, NUL-terminated and cut
+/// short if needed, as `snprintf` does. Returns the length of `token` in
+/// bytes.
+fn copy_token(token: &str, buf: *mut c_char, buf_len: usize) -> c_int {
+    if !buf.is_null() && buf_len > 0 {
+        let len = token.len().min(buf_len - 1);
+        unsafe {
+            ptr::copy_nonoverlapping(token.as_ptr() as *const c_char, buf, len);
+            *buf.add(len) = 0;
+        }
+    }
+    token.len() as c_int
+}
+
+/// Copies token `index` of `encoding` into `buf` like `snprintf`. Returns the
+/// length of the token, or -1 on error.
+#[no_mangle]
+pub extern "C" fn bert_encoding_token(
+    encoding: *const Encoding,
+    index: usize,
+    buf: *mut c_char,
+    buf_len: usize,
+) -> c_int {
+    guard(-1, || {
+        let encoding = non_null(encoding, "encoding")?;
+        let token = encoding.tokens.get(index).ok_or_else(|| {
+            FfiError::new(
+                ErrorCode::InvalidArgument,
+                format!("index {} out of {} tokens", index, encoding.len()),
+            )
+        })?;
+        Ok(copy_token(token, buf, buf_len))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn bert_tokenizer_vocab_size(tokenizer: *const c_void) -> usize {
+    guard(0, || Ok(self::tokenizer(tokenizer)?.vocab_size()))
+}
+
+/// Id of `token`, or -1 if it is not in the vocabulary or on error.
+#[no_mangle]
+pub extern "C" fn bert_tokenizer_token_to_id(
+    tokenizer: *const c_void,
+    token: *const c_char,
+) -> i64 {
+    guard(-1, || {
+        let tokenizer = self::tokenizer(tokenizer)?;
+        let token = c_str(token, "token")?;
+        Ok(tokenizer.token_to_id(&token).map_or(-1, |id| id as i64))
+    })
+}
+
+/// 1 if `token` is in the vocabulary, 0 if not, -1 on error.
+#[no_mangle]
+pub extern "C" fn bert_tokenizer_contains(tokenizer: *const c_void, token: *const c_char) -> c_int {
+    guard(-1, || {
+        let tokenizer = self::tokenizer(tokenizer)?;
+        let token = c_str(token, "token")?;
+        Ok(tokenizer.contains(&token) as c_int)
+    })
+}
+
+/// Copies the token of `id` into `buf` like `snprintf`. Returns the length of
+/// the token, or -1 if `id` is not in the vocabulary or on error.
+#[no_mangle]
+pub extern "C" fn bert_tokenizer_id_to_token(
+    tokenizer: *const c_void,
+    id: i64,
+    buf: *mut c_char,
+    buf_len: usize,
+) -> c_int {
+    guard(-1, || {
+        let tokenizer = self::tokenizer(tokenizer)?;
+        let token = if id < 0 {
+            None
+        } else {
+            tokenizer.id_to_token(id as usize)
+        };
+        Ok(token.map_or(-1, |token| copy_token(token, buf, buf_len)))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn get_input_ids() -> *mut i64 {
+    guard(ptr::null_mut(), || {
+        Ok(LAST_ENCODING.with(|encoding| encoding.borrow_mut().input_ids.as_mut_ptr()))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn get_input_mask() -> *mut i64 {
+    guard(ptr::null_mut(), || {
+        Ok(LAST_ENCODING.with(|encoding| encoding.borrow_mut().input_mask.as_mut_ptr()))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn get_segment_ids() -> *mut i64 {
+    guard(ptr::null_mut(), || {
+        Ok(LAST_ENCODING.with(|encoding| encoding.borrow_mut().segment_ids.as_mut_ptr()))
+    })
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use padding::PaddingParams;
     #[test]
     fn pipeline() {
         let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
-        let len = tokenizer.convert_pairs("你好", "", 0, false);
+        let len = tokenizer.convert_pairs("你好", "", 0, false).unwrap();
+        let encoding = tokenizer.encode("你好", 0).unwrap();
+        assert_eq!(len, encoding.len());
         unsafe {
             let input_ids = std::slice::from_raw_parts(get_input_ids(), len);
+            let input_mask = std::slice::from_raw_parts(get_input_mask(), len);
             let token_type_ids = std::slice::from_raw_parts(get_segment_ids(), len);
 
-            println!("{:?}", input_ids);
-            println!("{:?}", INPUT_IDS);
-            println!("{:?}", token_type_ids);
+            assert_eq!(input_ids, &encoding.input_ids[..]);
+            assert_eq!(input_mask, &encoding.input_mask[..]);
+            assert_eq!(token_type_ids, &encoding.segment_ids[..]);
+        }
+    }
+
+    #[test]
+    fn encoding_handle() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let handle = &tokenizer as *const FullTokenizer as *const c_void;
+        let text_a = CString::new("你好").unwrap();
+        let text_b = CString::new("hello world").unwrap();
+        let expected = tokenizer.encode_pair("你好", "hello world", 12).unwrap();
+
+        let encoding = bert_tokenizer_encode(handle, text_a.as_ptr(), text_b.as_ptr(), 12);
+        assert!(!encoding.is_null());
+        let len = bert_encoding_len(encoding);
+        assert_eq!(len, 12);
+        unsafe {
+            let input_ids = std::slice::from_raw_parts(bert_encoding_input_ids(encoding), len);
+            let input_mask = std::slice::from_raw_parts(bert_encoding_input_mask(encoding), len);
+            let segment_ids = std::slice::from_raw_parts(bert_encoding_segment_ids(encoding), len);
+            assert_eq!(input_ids, &expected.input_ids[..]);
+            assert_eq!(input_mask, &expected.input_mask[..]);
+            assert_eq!(segment_ids, &expected.segment_ids[..]);
         }
+        bert_encoding_free(encoding);
+
+        let mut input_ids = [-1; 16];
+        let mut segment_ids = [-1; 16];
+        let len = bert_tokenizer_encode_into(
+            handle,
+            text_a.as_ptr(),
+            ptr::null(),
+            0,
+            input_ids.as_mut_ptr(),
+            ptr::null_mut(),
+            segment_ids.as_mut_ptr(),
+            16,
+        );
+        assert_eq!(len, 4);
+        assert_eq!(input_ids[..5], [101, 872, 1962, 102, -1]);
+        assert_eq!(segment_ids[..5], [0, 0, 0, 0, -1]);
+
+        let len = bert_tokenizer_encode_into(
+            handle,
+            text_a.as_ptr(),
+            ptr::null(),
+            0,
+            input_ids.as_mut_ptr(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            3,
+        );
+        assert_eq!(len, -1);
+        let error = unsafe { CStr::from_ptr(bert_tokenizer_get_error()) };
+        assert!(error.to_string_lossy().contains("does not fit"));
+        assert_eq!(bert_tokenizer_get_error_code(), ErrorCode::BufferTooSmall);
+    }
+
+    #[test]
+    fn errors() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let handle = &tokenizer as *const FullTokenizer as *mut c_void;
+        let text = CString::new("hello").unwrap();
+
+        assert!(create_full_tokenizer(ptr::null(), 1).is_null());
+        assert_eq!(bert_tokenizer_get_error_code(), ErrorCode::NullPointer);
+        let missing = CString::new("no/such/vocab").unwrap();
+        assert!(create_full_tokenizer(missing.as_ptr(), 1).is_null());
+        assert_eq!(bert_tokenizer_get_error_code(), ErrorCode::LoadFailed);
+
+        assert_eq!(
+            convert_pairs(ptr::null_mut(), text.as_ptr(), ptr::null(), 0, 0),
+            -1
+        );
+        assert_eq!(bert_tokenizer_get_error_code(), ErrorCode::NullPointer);
+        let error = unsafe { CStr::from_ptr(bert_tokenizer_get_error()) };
+        assert_eq!(error.to_str().unwrap(), "`tokenizer` is null");
+        assert_eq!(convert_pairs(handle, text.as_ptr(), ptr::null(), 0, 1), -1);
+        assert_eq!(convert_pairs(handle, text.as_ptr(), ptr::null(), -1, 0), -1);
+        assert_eq!(bert_tokenizer_get_error_code(), ErrorCode::InvalidArgument);
+        assert_eq!(convert_pairs(handle, text.as_ptr(), ptr::null(), 1, 0), -1);
+        assert_eq!(bert_tokenizer_get_error_code(), ErrorCode::EncodeFailed);
+        assert_eq!(convert_pairs(handle, text.as_ptr(), ptr::null(), 0, 0), 3);
+        assert_eq!(bert_tokenizer_get_error_code(), ErrorCode::Ok);
+
+        assert_eq!(bert_encoding_len(ptr::null()), 0);
+        assert!(bert_encoding_input_ids(ptr::null()).is_null());
+        bert_encoding_free(ptr::null_mut());
+        drop_tokenizer(ptr::null_mut());
+
+        let mut len = 0;
+        let ids = convert_to_ids(handle, text.as_ptr(), &mut len);
+        assert_eq!(
+            unsafe { std::slice::from_raw_parts(ids, len as usize) },
+            [8701]
+        );
+        drop_ids(ids, len);
+
+        // interior NULs are dropped from the message
+        set_error(ErrorCode::InvalidArgument, "bad\0input");
+        let error = unsafe { CStr::from_ptr(bert_tokenizer_get_error()) };
+        assert_eq!(error.to_str().unwrap(), "badinput");
+    }
+
+    #[test]
+    fn vocab_lookups() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let handle = &tokenizer as *const FullTokenizer as *const c_void;
+        let hello = CString::new("hello").unwrap();
+        let missing = CString::new("no-such-token").unwrap();
+
+        assert_eq!(bert_tokenizer_vocab_size(handle), tokenizer.vocab_size());
+        assert_eq!(bert_tokenizer_token_to_id(handle, hello.as_ptr()), 8701);
+        assert_eq!(bert_tokenizer_token_to_id(handle, missing.as_ptr()), -1);
+        assert_eq!(bert_tokenizer_contains(handle, hello.as_ptr()), 1);
+        assert_eq!(bert_tokenizer_contains(handle, missing.as_ptr()), 0);
+        assert_eq!(bert_tokenizer_contains(handle, ptr::null()), -1);
+
+        let mut buf = [1 as c_char; 8];
+        let len = bert_tokenizer_id_to_token(handle, 8701, buf.as_mut_ptr(), buf.len());
+        assert_eq!(len, 5);
+        assert_eq!(
+            unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str(),
+            Ok("hello")
+        );
+        let len = bert_tokenizer_id_to_token(handle, 8701, buf.as_mut_ptr(), 3);
+        assert_eq!(len, 5);
+        assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str(), Ok("he"));
+        assert_eq!(
+            bert_tokenizer_id_to_token(handle, -5, buf.as_mut_ptr(), 8),
+            -1
+        );
+
+        let encoding = bert_tokenizer_encode(handle, hello.as_ptr(), ptr::null(), 0);
+        let tokens: Vec<String> = (0..bert_encoding_len(encoding))
+            .map(|i| {
+                assert!(bert_encoding_token(encoding, i, buf.as_mut_ptr(), buf.len()) >= 0);
+                unsafe { CStr::from_ptr(buf.as_ptr()) }
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(tokens, ["[CLS]", "hello", "[SEP]"]);
+        assert_eq!(bert_encoding_token(encoding, 3, buf.as_mut_ptr(), 8), -1);
+        bert_encoding_free(encoding);
+    }
+
+    #[test]
+    fn panics_are_caught() {
+        let result = guard(-1, || -> Result<c_int, FfiError> { panic!("boom") });
+        assert_eq!(result, -1);
+        assert_eq!(bert_tokenizer_get_error_code(), ErrorCode::Panic);
+        let error = unsafe { CStr::from_ptr(bert_tokenizer_get_error()) };
+        assert_eq!(error.to_str().unwrap(), "tokenizer panicked: boom");
+    }
+
+    #[test]
+    fn encode_batch() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let handle = &tokenizer as *const FullTokenizer as *const c_void;
+        let texts: Vec<CString> = ["hello", "你好", "hello world"]
+            .iter()
+            .map(|&text| CString::new(text).unwrap())
+            .collect();
+        let texts_a: Vec<*const c_char> = texts.iter().map(|text| text.as_ptr()).collect();
+        let texts_b = [ptr::null(), texts[2].as_ptr(), ptr::null()];
+
+        let mut input_ids = [-1i64; 18];
+        let mut segment_ids = [-1i64; 18];
+        let status = bert_tokenizer_encode_batch(
+            handle,
+            texts_a.as_ptr(),
+            texts_b.as_ptr(),
+            3,
+            6,
+            input_ids.as_mut_ptr(),
+            ptr::null_mut(),
+            segment_ids.as_mut_ptr(),
+        );
+        assert_eq!(status, 0);
+        assert_eq!(input_ids[..6], [101, 8701, 102, 0, 0, 0]);
+        assert_eq!(input_ids[6..12], [101, 872, 1962, 102, 8701, 102]);
+        assert_eq!(segment_ids[6..12], [0, 0, 0, 0, 1, 1]);
+        assert_eq!(input_ids[12..], [101, 8701, 8572, 102, 0, 0]);
+
+        let mut input_mask = [-1i32; 12];
+        let status = bert_tokenizer_encode_batch_i32(
+            handle,
+            texts_a.as_ptr(),
+            ptr::null(),
+            2,
+            6,
+            ptr::null_mut(),
+            input_mask.as_mut_ptr(),
+            ptr::null_mut(),
+        );
+        assert_eq!(status, 0);
+        assert_eq!(input_mask, [1, 1, 1, 0, 0, 0, 1, 1, 1, 1, 0, 0]);
+
+        // segment ids that overflow int32 leave every buffer untouched
+        let tokenizer = FullTokenizer::builder("vocab.txt")
+            .padding(PaddingParams {
+                pad_type_id: i64::from(i32::MAX) + 1,
+                ..PaddingParams::default()
+            })
+            .build()
+            .unwrap();
+        let handle = &tokenizer as *const FullTokenizer as *const c_void;
+        let mut input_ids = [-1i32; 6];
+        let mut input_mask = [-1i32; 6];
+        let mut segment_ids = [-1i32; 6];
+        let status = bert_tokenizer_encode_batch_i32(
+            handle,
+            texts_a.as_ptr(),
+            ptr::null(),
+            1,
+            6,
+            input_ids.as_mut_ptr(),
+            input_mask.as_mut_ptr(),
+            segment_ids.as_mut_ptr(),
+        );
+        assert_eq!(status, -1);
+        assert_eq!(bert_tokenizer_get_error_code(), ErrorCode::InvalidArgument);
+        assert_eq!(input_ids, [-1; 6]);
+        assert_eq!(input_mask, [-1; 6]);
+        assert_eq!(segment_ids, [-1; 6]);
     }
 }
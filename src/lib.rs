@@ -1,19 +1,59 @@
+// the pyo3 macros expand to `::core` paths, which resolve at the crate root
+// in the 2015 edition
+#[cfg(feature = "python")]
+extern crate core;
 extern crate indexmap;
+#[cfg(feature = "python")]
+extern crate numpy;
+#[cfg(feature = "python")]
+extern crate pyo3;
+extern crate rand;
+extern crate rand_chacha;
+extern crate rayon;
+extern crate serde_json;
 extern crate unicode_categories;
 extern crate unicode_normalization;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::ffi::CString;
 
 thread_local! {
-    pub static INPUT_IDS : RefCell<Vec<i64>> = RefCell::new(Vec::new());
-    pub static INPUT_MASK: RefCell<Vec<i64>> = RefCell::new(Vec::new());
-    pub static SEGMENT_IDS:RefCell<Vec<i64>> = RefCell::new(Vec::new());
-    pub static ERROR_MSG : RefCell<CString> = RefCell::new(CString::new("").unwrap());
+    static LAST_ENCODING: RefCell<Encoding> = RefCell::new(Encoding::default());
+    static ERROR_MSG : RefCell<CString> = RefCell::new(CString::default());
+    static ERROR_CODE: Cell<ErrorCode> = const { Cell::new(ErrorCode::Ok) };
 }
 
+/// Stores the result of the last `convert_pairs` call of this thread, backing
+/// the `get_input_ids`/`get_input_mask`/`get_segment_ids` ffi getters.
+fn set_last_encoding(encoding: Encoding) {
+    LAST_ENCODING.with(|last_encoding| *last_encoding.borrow_mut() = encoding);
+}
+
+mod builder;
+mod collator;
+mod encoding;
 mod ffi;
+mod padding;
+mod pretrained;
+mod pretraining;
+#[cfg(feature = "python")]
+mod python;
+mod tfrecord;
 mod tokenization;
+mod tokenizer_json;
+mod trainer;
+mod trie;
+mod truncation;
 
+pub use self::builder::{FullTokenizerBuilder, SpecialTokens};
+pub use self::collator::{MlmBatch, MlmCollator, IGNORE_INDEX};
+pub use self::encoding::Encoding;
+pub use self::padding::{PaddingDirection, PaddingParams, PaddingStrategy};
+pub use self::pretraining::{
+    ChineseWordSegmentation, Document, PretrainingDataGenerator, Sentence, TrainingInstance,
+};
+pub use self::tfrecord::{Example, Feature, TfRecordReader, TfRecordWriter};
 pub use self::tokenization::*;
+pub use self::trainer::WordpieceTrainer;
+pub use self::truncation::TruncationStrategy;
 
 pub use ffi::*;
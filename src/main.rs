@@ -0,0 +1,928 @@
+//! `bert-tokenize`: encodes text, TSV or JSONL input with a `FullTokenizer`
+//! and writes JSONL, `.npy` arrays or a flat binary id file.
+
+extern crate bert_tokenizer;
+extern crate rayon;
+extern crate serde_json;
+
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter, SeekFrom};
+use std::path::Path;
+use std::process;
+
+use bert_tokenizer::{
+    Encoding, FullTokenizer, FullTokenizerBuilder, PaddingDirection, PaddingStrategy,
+    SpecialTokens, TruncationStrategy,
+};
+use serde_json::{json, Value};
+
+const USAGE: &str = "\
+usage: bert-tokenize [options] (--vocab FILE | --tokenizer-json FILE | --pretrained-dir DIR) [INPUT...]
+
+Encodes every record of the INPUT files, or of stdin when there is none or
+INPUT is `-`, keeping the input order.
+
+input:
+  --format text|tsv|jsonl       one text per line, tab-separated columns or
+                                JSON objects (default: text)
+  --text-a FIELD                column index or name, or JSON key of the
+                                first text (default: 0 for tsv, text for jsonl)
+  --text-b FIELD                second text of sequence pairs
+  --header                      the first line of every TSV file names the
+                                columns
+
+tokenizer:
+  --vocab FILE                  newline-separated vocabulary
+  --tokenizer-json FILE         Hugging Face tokenizer.json
+  --pretrained-dir DIR          vocab.txt with its tokenizer_config.json
+  --lowercase true|false        (default: true)
+  --strip-accents true|false    (default: same as --lowercase)
+  --tokenize-chinese-chars true|false
+  --max-input-chars-per-word N
+  --continuing-subword-prefix P
+  --unk-token T, --cls-token T, --sep-token T, --pad-token T, --mask-token T
+  --added-token T               matched verbatim, repeatable
+                                (these options require --vocab)
+
+truncation and padding:
+  --max-seq-len N               0 keeps every token (default: 128)
+  --truncation STRATEGY         longest-first, only-first, only-second,
+                                do-not-truncate or head-tail:HEAD:TAIL
+  --padding STRATEGY            max-seq-len, batch-longest, fixed:N or none
+  --padding-side right|left
+  --pad-to-multiple-of N
+
+output:
+  --output PATH                 file for jsonl (default: stdout), directory
+                                for npy, file prefix for bin
+  --output-format jsonl|npy|bin
+                                jsonl: input_ids, input_mask, segment_ids;
+                                npy: PATH/{input_ids,input_mask,segment_ids}.npy,
+                                which requires equal lengths;
+                                bin: PATH.bin holds the input ids of all
+                                records back to back, PATH.idx the u64 offset
+                                of every record and the total
+  --dtype int64|int32|uint16    integer type of npy and bin (default: int64)
+  --with-tokens                 adds the tokens and offsets to jsonl
+  --batch-size N                records encoded in parallel at a time, also
+                                the batch of batch-longest (default: 10000)
+  --threads N                   (default: one per core)
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InputFormat {
+    Text,
+    Tsv,
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Jsonl,
+    Npy,
+    Bin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dtype {
+    Int64,
+    Int32,
+    Uint16,
+}
+
+impl Dtype {
+    fn descr(self) -> &'static str {
+        match self {
+            Dtype::Int64 => "<i8",
+            Dtype::Int32 => "<i4",
+            Dtype::Uint16 => "<u2",
+        }
+    }
+
+    fn write<W: Write>(self, writer: &mut W, values: &[i64]) -> Result<(), Box<dyn Error>> {
+        for &value in values {
+            match self {
+                Dtype::Int64 => writer.write_all(&value.to_le_bytes())?,
+                Dtype::Int32 if value >= i32::MIN as i64 && value <= i32::MAX as i64 => {
+                    writer.write_all(&(value as i32).to_le_bytes())?
+                }
+                Dtype::Uint16 if value >= 0 && value <= u16::MAX as i64 => {
+                    writer.write_all(&(value as u16).to_le_bytes())?
+                }
+                _ => return Err(format!("{} does not fit in {}", value, self.descr()).into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A TSV column or JSON key.
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Index(usize),
+    Name(String),
+}
+
+impl Field {
+    fn parse(field: &str) -> Field {
+        match field.parse() {
+            Ok(index) => Field::Index(index),
+            Err(_) => Field::Name(field.to_string()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Args {
+    inputs: Vec<String>,
+    format: InputFormat,
+    text_a: Option<Field>,
+    text_b: Option<Field>,
+    header: bool,
+    vocab: Option<String>,
+    tokenizer_json: Option<String>,
+    pretrained_dir: Option<String>,
+    lowercase: Option<bool>,
+    strip_accents: Option<bool>,
+    tokenize_chinese_chars: Option<bool>,
+    max_input_chars_per_word: Option<usize>,
+    continuing_subword_prefix: Option<String>,
+    /// `(role, token)` of the special tokens given.
+    special_tokens: Vec<(String, String)>,
+    added_tokens: Vec<String>,
+    max_seq_len: usize,
+    truncation: Option<TruncationStrategy>,
+    padding: Option<PaddingStrategy>,
+    padding_side: Option<PaddingDirection>,
+    pad_to_multiple_of: Option<usize>,
+    output: Option<String>,
+    output_format: OutputFormat,
+    dtype: Dtype,
+    with_tokens: bool,
+    batch_size: usize,
+    threads: Option<usize>,
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("expected true or false, got `{}`", value)),
+    }
+}
+
+fn parse_usize(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("expected a number, got `{}`", value))
+}
+
+fn parse_truncation(value: &str) -> Result<TruncationStrategy, String> {
+    match value {
+        "longest-first" => Ok(TruncationStrategy::LongestFirst),
+        "only-first" => Ok(TruncationStrategy::OnlyFirst),
+        "only-second" => Ok(TruncationStrategy::OnlySecond),
+        "do-not-truncate" => Ok(TruncationStrategy::DoNotTruncate),
+        _ if value.starts_with("head-tail:") => {
+            let mut lengths = value["head-tail:".len()..].splitn(2, ':');
+            let head = parse_usize(lengths.next().unwrap())?;
+            let tail = lengths
+                .next()
+                .ok_or_else(|| "head-tail needs HEAD:TAIL".to_string())?;
+            Ok(TruncationStrategy::HeadTail {
+                head,
+                tail: parse_usize(tail)?,
+            })
+        }
+        _ => Err(format!("unknown truncation strategy `{}`", value)),
+    }
+}
+
+fn parse_padding(value: &str) -> Result<PaddingStrategy, String> {
+    match value {
+        "max-seq-len" => Ok(PaddingStrategy::MaxSeqLen),
+        "batch-longest" => Ok(PaddingStrategy::BatchLongest),
+        "none" => Ok(PaddingStrategy::DoNotPad),
+        _ if value.starts_with("fixed:") => Ok(PaddingStrategy::Fixed(parse_usize(
+            &value["fixed:".len()..],
+        )?)),
+        _ => Err(format!("unknown padding strategy `{}`", value)),
+    }
+}
+
+impl Args {
+    /// Parses the command line; `None` for `--help`.
+    fn parse<I: Iterator<Item = String>>(mut argv: I) -> Result<Option<Args>, String> {
+        let mut args = Args {
+            inputs: Vec::new(),
+            format: InputFormat::Text,
+            text_a: None,
+            text_b: None,
+            header: false,
+            vocab: None,
+            tokenizer_json: None,
+            pretrained_dir: None,
+            lowercase: None,
+            strip_accents: None,
+            tokenize_chinese_chars: None,
+            max_input_chars_per_word: None,
+            continuing_subword_prefix: None,
+            special_tokens: Vec::new(),
+            added_tokens: Vec::new(),
+            max_seq_len: 128,
+            truncation: None,
+            padding: None,
+            padding_side: None,
+            pad_to_multiple_of: None,
+            output: None,
+            output_format: OutputFormat::Jsonl,
+            dtype: Dtype::Int64,
+            with_tokens: false,
+            batch_size: 10000,
+            threads: None,
+        };
+        while let Some(arg) = argv.next() {
+            if arg == "-" || !arg.starts_with("--") {
+                args.inputs.push(arg);
+                continue;
+            }
+            match arg.as_str() {
+                "--header" => args.header = true,
+                "--with-tokens" => args.with_tokens = true,
+                "--help" => return Ok(None),
+                _ => {
+                    let value = argv
+                        .next()
+                        .ok_or_else(|| format!("{} needs a value", arg))?;
+                    args.set(&arg, value)
+                        .map_err(|e| format!("{}: {}", arg, e))?;
+                }
+            }
+        }
+
+        let sources = [&args.vocab, &args.tokenizer_json, &args.pretrained_dir];
+        match sources.iter().filter(|source| source.is_some()).count() {
+            1 => {}
+            0 => return Err("no tokenizer given".to_string()),
+            _ => {
+                return Err(
+                    "give only one of --vocab, --tokenizer-json and --pretrained-dir".to_string(),
+                )
+            }
+        }
+        let has_builder_options = args.lowercase.is_some()
+            || args.strip_accents.is_some()
+            || args.tokenize_chinese_chars.is_some()
+            || args.max_input_chars_per_word.is_some()
+            || args.continuing_subword_prefix.is_some()
+            || !args.special_tokens.is_empty()
+            || !args.added_tokens.is_empty();
+        if has_builder_options && args.vocab.is_none() {
+            return Err("tokenizer options require --vocab".to_string());
+        }
+        if args.format == InputFormat::Text && (args.text_a.is_some() || args.text_b.is_some()) {
+            return Err("--text-a and --text-b require --format tsv or jsonl".to_string());
+        }
+        if args.output_format != OutputFormat::Jsonl && args.output.is_none() {
+            return Err("npy and bin output require --output".to_string());
+        }
+        if args.batch_size == 0 {
+            return Err("--batch-size must be positive".to_string());
+        }
+        Ok(Some(args))
+    }
+
+    fn set(&mut self, arg: &str, value: String) -> Result<(), String> {
+        match arg {
+            "--format" => {
+                self.format = match value.as_str() {
+                    "text" => InputFormat::Text,
+                    "tsv" => InputFormat::Tsv,
+                    "jsonl" => InputFormat::Jsonl,
+                    _ => return Err(format!("unknown input format `{}`", value)),
+                }
+            }
+            "--text-a" => self.text_a = Some(Field::parse(&value)),
+            "--text-b" => self.text_b = Some(Field::parse(&value)),
+            "--vocab" => self.vocab = Some(value),
+            "--tokenizer-json" => self.tokenizer_json = Some(value),
+            "--pretrained-dir" => self.pretrained_dir = Some(value),
+            "--lowercase" => self.lowercase = Some(parse_bool(&value)?),
+            "--strip-accents" => self.strip_accents = Some(parse_bool(&value)?),
+            "--tokenize-chinese-chars" => self.tokenize_chinese_chars = Some(parse_bool(&value)?),
+            "--max-input-chars-per-word" => {
+                self.max_input_chars_per_word = Some(parse_usize(&value)?)
+            }
+            "--continuing-subword-prefix" => self.continuing_subword_prefix = Some(value),
+            "--unk-token" | "--cls-token" | "--sep-token" | "--pad-token" | "--mask-token" => self
+                .special_tokens
+                .push((arg[2..].replace('-', "_"), value)),
+            "--added-token" => self.added_tokens.push(value),
+            "--max-seq-len" => self.max_seq_len = parse_usize(&value)?,
+            "--truncation" => self.truncation = Some(parse_truncation(&value)?),
+            "--padding" => self.padding = Some(parse_padding(&value)?),
+            "--padding-side" => {
+                self.padding_side = match value.as_str() {
+                    "right" => Some(PaddingDirection::Right),
+                    "left" => Some(PaddingDirection::Left),
+                    _ => return Err(format!("expected right or left, got `{}`", value)),
+                }
+            }
+            "--pad-to-multiple-of" => self.pad_to_multiple_of = Some(parse_usize(&value)?),
+            "--output" => self.output = Some(value),
+            "--output-format" => {
+                self.output_format = match value.as_str() {
+                    "jsonl" => OutputFormat::Jsonl,
+                    "npy" => OutputFormat::Npy,
+                    "bin" => OutputFormat::Bin,
+                    _ => return Err(format!("unknown output format `{}`", value)),
+                }
+            }
+            "--dtype" => {
+                self.dtype = match value.as_str() {
+                    "int64" => Dtype::Int64,
+                    "int32" => Dtype::Int32,
+                    "uint16" => Dtype::Uint16,
+                    _ => return Err(format!("unknown dtype `{}`", value)),
+                }
+            }
+            "--batch-size" => self.batch_size = parse_usize(&value)?,
+            "--threads" => self.threads = Some(parse_usize(&value)?),
+            _ => return Err("unknown option".to_string()),
+        }
+        Ok(())
+    }
+}
+
+fn load_tokenizer(args: &Args) -> Result<FullTokenizer, Box<dyn Error>> {
+    let mut tokenizer = if let Some(ref path) = args.tokenizer_json {
+        FullTokenizer::from_tokenizer_json(path)?
+    } else if let Some(ref dir) = args.pretrained_dir {
+        FullTokenizer::from_pretrained_dir(dir)?
+    } else {
+        let vocab_file = args.vocab.as_ref().unwrap();
+        let mut special_tokens = SpecialTokens::default();
+        for (role, token) in &args.special_tokens {
+            match role.as_str() {
+                "unk_token" => special_tokens.unk_token = token.clone(),
+                "cls_token" => special_tokens.cls_token = token.clone(),
+                "sep_token" => special_tokens.sep_token = token.clone(),
+                "pad_token" => special_tokens.pad_token = Some(token.clone()),
+                _ => special_tokens.mask_token = Some(token.clone()),
+            }
+        }
+
+        let lowercase = args.lowercase.unwrap_or(true);
+        let mut builder = FullTokenizerBuilder::new(vocab_file)
+            .do_lower_case(lowercase)
+            .strip_accents(args.strip_accents.unwrap_or(lowercase))
+            .special_tokens(special_tokens)
+            .optional_default_tokens(true)
+            .added_tokens(args.added_tokens.clone());
+        if let Some(tokenize_chinese_chars) = args.tokenize_chinese_chars {
+            builder = builder.tokenize_chinese_chars(tokenize_chinese_chars);
+        }
+        if let Some(max_input_chars_per_word) = args.max_input_chars_per_word {
+            builder = builder.max_input_chars_per_word(max_input_chars_per_word);
+        }
+        if let Some(ref prefix) = args.continuing_subword_prefix {
+            builder = builder.continuing_subword_prefix(prefix);
+        }
+        builder.build()?
+    };
+
+    if let Some(truncation) = args.truncation {
+        tokenizer.set_truncation_strategy(truncation);
+    }
+    let mut padding = tokenizer.padding().clone();
+    if let Some(strategy) = args.padding {
+        padding.strategy = strategy;
+    }
+    if let Some(direction) = args.padding_side {
+        padding.direction = direction;
+    }
+    if args.pad_to_multiple_of.is_some() {
+        padding.pad_to_multiple_of = args.pad_to_multiple_of;
+    }
+    tokenizer.set_padding(padding);
+    Ok(tokenizer)
+}
+
+/// Extracts text_a and text_b from the lines of one input.
+struct RecordParser {
+    format: InputFormat,
+    text_a: Field,
+    text_b: Option<Field>,
+}
+
+impl RecordParser {
+    fn new(args: &Args) -> RecordParser {
+        let default_text_a = match args.format {
+            InputFormat::Jsonl => Field::Name("text".to_string()),
+            _ => Field::Index(0),
+        };
+        RecordParser {
+            format: args.format,
+            text_a: args.text_a.clone().unwrap_or(default_text_a),
+            text_b: args.text_b.clone(),
+        }
+    }
+
+    /// Resolves column names with the header line of a TSV file.
+    fn with_header(&self, header: &str) -> Result<RecordParser, String> {
+        let columns: Vec<&str> = header.split('\t').collect();
+        let resolve = |field: &Field| match *field {
+            Field::Index(index) => Ok(Field::Index(index)),
+            Field::Name(ref name) => columns
+                .iter()
+                .position(|column| column == name)
+                .map(Field::Index)
+                .ok_or_else(|| format!("no column `{}` in the header", name)),
+        };
+        Ok(RecordParser {
+            format: self.format,
+            text_a: resolve(&self.text_a)?,
+            text_b: match self.text_b {
+                Some(ref field) => Some(resolve(field)?),
+                None => None,
+            },
+        })
+    }
+
+    /// Returns `None` for lines without a record, the blank lines of JSONL.
+    fn parse(&self, line: &str) -> Result<Option<(String, Option<String>)>, String> {
+        match self.format {
+            InputFormat::Text => Ok(Some((line.to_string(), None))),
+            InputFormat::Tsv => {
+                let columns: Vec<&str> = line.split('\t').collect();
+                let column = |field: &Field| match *field {
+                    Field::Index(index) => columns
+                        .get(index)
+                        .map(|column| column.to_string())
+                        .ok_or_else(|| format!("no column {}", index)),
+                    Field::Name(ref name) => Err(format!("column `{}` needs --header", name)),
+                };
+                let text_b = match self.text_b {
+                    Some(ref field) => Some(column(field)?),
+                    None => None,
+                };
+                Ok(Some((column(&self.text_a)?, text_b)))
+            }
+            InputFormat::Jsonl => {
+                if line.trim().is_empty() {
+                    return Ok(None);
+                }
+                let record: Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+                let text = |field: &Field| {
+                    let key = match *field {
+                        Field::Name(ref name) => name.clone(),
+                        Field::Index(index) => index.to_string(),
+                    };
+                    record
+                        .get(&key)
+                        .and_then(Value::as_str)
+                        .map(str::to_string)
+                        .ok_or_else(|| format!("no string field `{}`", key))
+                };
+                let text_b = match self.text_b {
+                    Some(ref field) => Some(text(field)?),
+                    None => None,
+                };
+                Ok(Some((text(&self.text_a)?, text_b)))
+            }
+        }
+    }
+}
+
+trait Sink {
+    fn write_batch(&mut self, encodings: &[Encoding]) -> Result<(), Box<dyn Error>>;
+    fn finish(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+struct JsonlSink {
+    writer: Box<dyn Write>,
+    with_tokens: bool,
+}
+
+impl Sink for JsonlSink {
+    fn write_batch(&mut self, encodings: &[Encoding]) -> Result<(), Box<dyn Error>> {
+        for encoding in encodings {
+            let mut record = json!({
+                "input_ids": encoding.input_ids,
+                "input_mask": encoding.input_mask,
+                "segment_ids": encoding.segment_ids,
+            });
+            if self.with_tokens {
+                record["tokens"] = json!(encoding.tokens);
+                record["offsets"] = json!(encoding.offsets);
+            }
+            serde_json::to_writer(&mut self.writer, &record)?;
+            self.writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Size of the `.npy` header, which is written again with the final shape
+/// once all rows are known.
+const NPY_HEADER_LEN: usize = 128;
+
+fn npy_header(dtype: Dtype, rows: usize, cols: usize) -> Vec<u8> {
+    let dict = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        dtype.descr(),
+        rows,
+        cols
+    );
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&((NPY_HEADER_LEN - 10) as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header.resize(NPY_HEADER_LEN - 1, b' ');
+    header.push(b'\n');
+    header
+}
+
+/// A 2D `.npy` array written row by row.
+struct NpyWriter {
+    writer: BufWriter<File>,
+    rows: usize,
+    cols: Option<usize>,
+}
+
+impl NpyWriter {
+    fn create(path: &Path, dtype: Dtype) -> Result<NpyWriter, Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&npy_header(dtype, 0, 0))?;
+        Ok(NpyWriter {
+            writer,
+            rows: 0,
+            cols: None,
+        })
+    }
+
+    fn write_row(&mut self, row: &[i64], dtype: Dtype) -> Result<(), Box<dyn Error>> {
+        match self.cols {
+            Some(cols) if cols != row.len() => {
+                return Err(format!(
+                    "record {} has {} tokens, not {} as the ones before; npy output needs \
+                     --padding max-seq-len or fixed:N",
+                    self.rows + 1,
+                    row.len(),
+                    cols
+                )
+                .into())
+            }
+            _ => self.cols = Some(row.len()),
+        }
+        dtype.write(&mut self.writer, row)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self, dtype: Dtype) -> Result<(), Box<dyn Error>> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        let header = npy_header(dtype, self.rows, self.cols.unwrap_or(0));
+        self.writer.write_all(&header)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+struct NpySink {
+    input_ids: NpyWriter,
+    input_mask: NpyWriter,
+    segment_ids: NpyWriter,
+    dtype: Dtype,
+}
+
+impl Sink for NpySink {
+    fn write_batch(&mut self, encodings: &[Encoding]) -> Result<(), Box<dyn Error>> {
+        for encoding in encodings {
+            self.input_ids.write_row(&encoding.input_ids, self.dtype)?;
+            self.input_mask
+                .write_row(&encoding.input_mask, self.dtype)?;
+            self.segment_ids
+                .write_row(&encoding.segment_ids, self.dtype)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.input_ids.finish(self.dtype)?;
+        self.input_mask.finish(self.dtype)?;
+        self.segment_ids.finish(self.dtype)
+    }
+}
+
+struct BinSink {
+    ids: BufWriter<File>,
+    index: BufWriter<File>,
+    offset: u64,
+    dtype: Dtype,
+}
+
+impl Sink for BinSink {
+    fn write_batch(&mut self, encodings: &[Encoding]) -> Result<(), Box<dyn Error>> {
+        for encoding in encodings {
+            self.index.write_all(&self.offset.to_le_bytes())?;
+            self.dtype.write(&mut self.ids, &encoding.input_ids)?;
+            self.offset += encoding.input_ids.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.index.write_all(&self.offset.to_le_bytes())?;
+        self.index.flush()?;
+        self.ids.flush()?;
+        Ok(())
+    }
+}
+
+fn create_sink(args: &Args) -> Result<Box<dyn Sink>, Box<dyn Error>> {
+    Ok(match args.output_format {
+        OutputFormat::Jsonl => {
+            let writer: Box<dyn Write> = match args.output {
+                Some(ref path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(BufWriter::new(io::stdout())),
+            };
+            Box::new(JsonlSink {
+                writer,
+                with_tokens: args.with_tokens,
+            })
+        }
+        OutputFormat::Npy => {
+            let dir = Path::new(args.output.as_ref().unwrap());
+            fs::create_dir_all(dir)?;
+            Box::new(NpySink {
+                input_ids: NpyWriter::create(&dir.join("input_ids.npy"), args.dtype)?,
+                input_mask: NpyWriter::create(&dir.join("input_mask.npy"), args.dtype)?,
+                segment_ids: NpyWriter::create(&dir.join("segment_ids.npy"), args.dtype)?,
+                dtype: args.dtype,
+            })
+        }
+        OutputFormat::Bin => {
+            let prefix = args.output.as_ref().unwrap();
+            Box::new(BinSink {
+                ids: BufWriter::new(File::create(format!("{}.bin", prefix))?),
+                index: BufWriter::new(File::create(format!("{}.idx", prefix))?),
+                offset: 0,
+                dtype: args.dtype,
+            })
+        }
+    })
+}
+
+/// Encodes a batch of records on the rayon thread pool, in input order.
+fn encode(
+    tokenizer: &FullTokenizer,
+    records: &[(String, Option<String>)],
+    max_seq_len: usize,
+) -> Result<Vec<Encoding>, Box<dyn Error + Send + Sync>> {
+    if records.iter().all(|(_, text_b)| text_b.is_none()) {
+        let texts: Vec<&str> = records.iter().map(|(text_a, _)| text_a.as_str()).collect();
+        tokenizer.encode_batch(&texts, max_seq_len)
+    } else {
+        let pairs: Vec<(&str, &str)> = records
+            .iter()
+            .map(|(text_a, text_b)| (text_a.as_str(), text_b.as_ref().map_or("", String::as_str)))
+            .collect();
+        tokenizer.encode_pair_batch(&pairs, max_seq_len)
+    }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+    }
+    let tokenizer = load_tokenizer(args)?;
+    let mut sink = create_sink(args)?;
+    let parser = RecordParser::new(args);
+
+    let inputs = if args.inputs.is_empty() {
+        vec!["-".to_string()]
+    } else {
+        args.inputs.clone()
+    };
+    let mut records = Vec::with_capacity(args.batch_size);
+    let mut first_record = 1;
+    for input in &inputs {
+        let reader: Box<dyn BufRead> = match input.as_str() {
+            "-" => Box::new(BufReader::new(io::stdin())),
+            path => Box::new(BufReader::new(File::open(path)?)),
+        };
+        let mut lines = reader.lines();
+        let header_parser;
+        let parser = if args.format == InputFormat::Tsv && args.header {
+            header_parser = match lines.next() {
+                Some(header) => parser
+                    .with_header(&header?)
+                    .map_err(|e| format!("{}:1: {}", input, e))?,
+                None => continue,
+            };
+            &header_parser
+        } else {
+            &parser
+        };
+        for (i, line) in lines.enumerate() {
+            let line_no = i + 1 + (args.header as usize);
+            let record = parser
+                .parse(&line?)
+                .map_err(|e| format!("{}:{}: {}", input, line_no, e))?;
+            records.extend(record);
+            if records.len() == args.batch_size {
+                let encodings = encode(&tokenizer, &records, args.max_seq_len)
+                    .map_err(|e| format!("records {}..: {}", first_record, e))?;
+                sink.write_batch(&encodings)?;
+                first_record += records.len();
+                records.clear();
+            }
+        }
+    }
+    if !records.is_empty() {
+        let encodings = encode(&tokenizer, &records, args.max_seq_len)
+            .map_err(|e| format!("records {}..: {}", first_record, e))?;
+        sink.write_batch(&encodings)?;
+    }
+    sink.finish()
+}
+
+fn main() {
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(message) => {
+            eprint!("bert-tokenize: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(&args) {
+        eprintln!("bert-tokenize: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn parse(args: &str) -> Result<Args, String> {
+        Args::parse(args.split_whitespace().map(str::to_string)).map(|args| args.unwrap())
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse(
+            "--vocab vocab.txt --format tsv --text-a 1 --text-b b --truncation head-tail:3:5 \
+             --padding fixed:16 --lowercase false a.tsv -",
+        )
+        .unwrap();
+        assert_eq!(args.inputs, ["a.tsv", "-"]);
+        assert_eq!(args.text_a, Some(Field::Index(1)));
+        assert_eq!(args.text_b, Some(Field::Name("b".to_string())));
+        assert_eq!(
+            args.truncation,
+            Some(TruncationStrategy::HeadTail { head: 3, tail: 5 })
+        );
+        assert_eq!(args.padding, Some(PaddingStrategy::Fixed(16)));
+        assert_eq!(args.lowercase, Some(false));
+
+        assert!(parse("a.txt").is_err());
+        assert!(parse("--vocab vocab.txt --tokenizer-json tokenizer.json").is_err());
+        assert!(parse("--pretrained-dir model --lowercase false").is_err());
+        assert!(parse("--vocab vocab.txt --text-a 1").is_err());
+        assert!(parse("--vocab vocab.txt --output-format npy").is_err());
+        assert!(parse("--vocab vocab.txt --padding fixed:x").is_err());
+        assert!(parse("--vocab vocab.txt --truncation head-tail:3").is_err());
+        assert!(parse("--vocab vocab.txt --max-seq-len").is_err());
+        let help = Args::parse(vec!["a.txt".to_string(), "--help".to_string()].into_iter());
+        assert!(help.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_record_parser() {
+        let args = parse("--vocab vocab.txt --format tsv --text-a q --text-b 2").unwrap();
+        let parser = RecordParser::new(&args).with_header("id\tq\tp").unwrap();
+        assert_eq!(
+            parser.parse("7\thello\tworld").unwrap(),
+            Some(("hello".to_string(), Some("world".to_string())))
+        );
+        assert!(parser.parse("7\thello").is_err());
+        assert!(RecordParser::new(&args).parse("7\thello\tworld").is_err());
+        assert!(RecordParser::new(&args).with_header("id\tp").is_err());
+
+        let args = parse("--vocab vocab.txt --format jsonl").unwrap();
+        let parser = RecordParser::new(&args);
+        assert_eq!(
+            parser.parse(r#"{"text": "你好", "label": 1}"#).unwrap(),
+            Some(("你好".to_string(), None))
+        );
+        assert_eq!(parser.parse("").unwrap(), None);
+        assert!(parser.parse(r#"{"label": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_npy_header() {
+        let header = npy_header(Dtype::Int32, 1000, 128);
+        assert_eq!(header.len(), NPY_HEADER_LEN);
+        assert_eq!(&header[..8], b"\x93NUMPY\x01\x00");
+        assert_eq!(header[8] as usize + 10, NPY_HEADER_LEN);
+        assert!(String::from_utf8(header[10..].to_vec())
+            .unwrap()
+            .starts_with("{'descr': '<i4', 'fortran_order': False, 'shape': (1000, 128), }"));
+        assert_eq!(header.last(), Some(&b'\n'));
+    }
+
+    #[test]
+    fn test_run() {
+        let dir = env::temp_dir().join(format!("bert_tokenize_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.txt");
+        fs::write(&input, "hello world\n你好\n\nhello\n").unwrap();
+        let input = input.to_str().unwrap();
+        let output = dir.join("output");
+        let output = output.to_str().unwrap();
+
+        let args = parse(&format!(
+            "--vocab vocab.txt --max-seq-len 6 --batch-size 2 --output {} {}",
+            output, input
+        ))
+        .unwrap();
+        run(&args).unwrap();
+        let lines: Vec<Value> = fs::read_to_string(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["input_ids"], json!([101, 8701, 8572, 102, 0, 0]));
+        assert_eq!(lines[1]["input_ids"], json!([101, 872, 1962, 102, 0, 0]));
+        assert_eq!(lines[2]["input_mask"], json!([1, 1, 0, 0, 0, 0]));
+        assert_eq!(lines[3]["segment_ids"], json!([0, 0, 0, 0, 0, 0]));
+
+        let args = parse(&format!(
+            "--vocab vocab.txt --padding none --dtype uint16 --output-format bin --output {} {}",
+            output, input
+        ))
+        .unwrap();
+        run(&args).unwrap();
+        let ids = fs::read(format!("{}.bin", output)).unwrap();
+        let index = fs::read(format!("{}.idx", output)).unwrap();
+        let ids: Vec<u16> = ids
+            .chunks(2)
+            .map(|id| u16::from_le_bytes([id[0], id[1]]))
+            .collect();
+        let index: Vec<u64> = index
+            .chunks(8)
+            .map(|offset| {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(offset);
+                u64::from_le_bytes(bytes)
+            })
+            .collect();
+        assert_eq!(index, [0, 4, 8, 10, 13]);
+        assert_eq!(&ids[4..8], [101, 872, 1962, 102]);
+
+        let npy_dir = dir.join("npy");
+        let npy_dir = npy_dir.to_str().unwrap();
+        let args = parse(&format!(
+            "--vocab vocab.txt --max-seq-len 8 --output-format npy --dtype int32 --output {} {}",
+            npy_dir, input
+        ))
+        .unwrap();
+        run(&args).unwrap();
+        let input_ids = fs::read(dir.join("npy/input_ids.npy")).unwrap();
+        assert_eq!(
+            &input_ids[..NPY_HEADER_LEN],
+            &npy_header(Dtype::Int32, 4, 8)[..]
+        );
+        assert_eq!(input_ids.len(), NPY_HEADER_LEN + 4 * 8 * 4);
+
+        // batches of two records padded to different lengths
+        let args = parse(&format!(
+            "--vocab vocab.txt --padding batch-longest --batch-size 2 --output-format npy \
+             --output {} {}",
+            npy_dir, input
+        ))
+        .unwrap();
+        let error = run(&args).err().unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("record 3 has 3 tokens, not 4"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
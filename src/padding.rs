@@ -0,0 +1,96 @@
+/// Length encodings are padded to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaddingStrategy {
+    /// Never pads.
+    DoNotPad,
+    /// Pads to the `max_seq_len` given to `encode`, unless it is 0. The
+    /// default.
+    #[default]
+    MaxSeqLen,
+    /// Pads to a fixed length.
+    Fixed(usize),
+    /// Pads every encoding of a batch to the longest one; single encodings
+    /// are not padded.
+    BatchLongest,
+}
+
+/// Side padding is added on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaddingDirection {
+    #[default]
+    Right,
+    Left,
+}
+
+/// Padding configuration of a `FullTokenizer`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PaddingParams {
+    pub strategy: PaddingStrategy,
+    pub direction: PaddingDirection,
+    /// Rounds the padded length up to a multiple of this, e.g. 8 for tensor
+    /// cores.
+    pub pad_to_multiple_of: Option<usize>,
+    /// Input id of padding; the id of the pad token when unset.
+    pub pad_id: Option<i64>,
+    /// Segment id of padding.
+    pub pad_type_id: i64,
+    /// Input mask value of padding.
+    pub pad_mask_value: i64,
+}
+
+impl PaddingParams {
+    fn round_up(&self, len: usize) -> usize {
+        match self.pad_to_multiple_of {
+            Some(multiple) if multiple > 0 => len.div_ceil(multiple) * multiple,
+            _ => len,
+        }
+    }
+
+    /// Padded length of a single encoding of `len` tokens.
+    pub(crate) fn target_len(&self, len: usize, max_seq_len: usize) -> usize {
+        let target = match self.strategy {
+            PaddingStrategy::DoNotPad => return len,
+            PaddingStrategy::MaxSeqLen => len.max(max_seq_len),
+            PaddingStrategy::Fixed(fixed_len) => len.max(fixed_len),
+            PaddingStrategy::BatchLongest => len,
+        };
+        self.round_up(target)
+    }
+
+    /// Padded length of every encoding of a batch whose longest encoding
+    /// has `longest` tokens, if the batch is padded as a whole.
+    pub(crate) fn batch_target_len(&self, longest: usize) -> Option<usize> {
+        match self.strategy {
+            PaddingStrategy::BatchLongest => Some(self.round_up(longest)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_target_len() {
+        let mut params = PaddingParams::default();
+        assert_eq!(params.target_len(5, 0), 5);
+        assert_eq!(params.target_len(5, 16), 16);
+        assert_eq!(params.batch_target_len(7), None);
+
+        params.pad_to_multiple_of = Some(8);
+        assert_eq!(params.target_len(5, 0), 8);
+        assert_eq!(params.target_len(5, 10), 16);
+
+        params.strategy = PaddingStrategy::Fixed(12);
+        assert_eq!(params.target_len(5, 10), 16);
+        assert_eq!(params.target_len(17, 10), 24);
+
+        params.strategy = PaddingStrategy::BatchLongest;
+        assert_eq!(params.target_len(5, 10), 8);
+        assert_eq!(params.batch_target_len(9), Some(16));
+
+        params.strategy = PaddingStrategy::DoNotPad;
+        assert_eq!(params.target_len(5, 10), 5);
+    }
+}
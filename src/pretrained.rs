@@ -0,0 +1,275 @@
+use serde_json::{self, Map, Value};
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use builder::{FullTokenizerBuilder, SpecialTokens};
+use tokenization::{FullTokenizer, Vocab};
+
+/// Reads `dir/name` as a JSON object, or returns `None` if there is no such
+/// file.
+fn read_json_object(dir: &Path, name: &str) -> Result<Option<Map<String, Value>>, Box<dyn Error>> {
+    let path = dir.join(name);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let file = File::open(&path)?;
+    match serde_json::from_reader(BufReader::new(file))? {
+        Value::Object(object) => Ok(Some(object)),
+        _ => Err(format!("{}: expected a JSON object", path.display()).into()),
+    }
+}
+
+fn invalid(file: &str, key: &str) -> Box<dyn Error> {
+    format!("{}: invalid `{}`", file, key).into()
+}
+
+/// A special token, saved either as a plain string or as a serialized
+/// `AddedToken` with a `content` field.
+fn token_content<'a>(value: &'a Value, file: &str, key: &str) -> Result<&'a str, Box<dyn Error>> {
+    match *value {
+        Value::String(ref token) => Ok(token),
+        Value::Object(ref token) => token
+            .get("content")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid(file, key)),
+        _ => Err(invalid(file, key)),
+    }
+}
+
+/// Special token names of a `tokenizer_config.json` or
+/// `special_tokens_map.json`, where a null token disables its role.
+fn apply_special_tokens(
+    special_tokens: &mut SpecialTokens,
+    config: &Map<String, Value>,
+    file: &str,
+) -> Result<(), Box<dyn Error>> {
+    for &key in &["unk_token", "cls_token", "sep_token"] {
+        if let Some(value) = config.get(key) {
+            let token = token_content(value, file, key)?.to_string();
+            match key {
+                "unk_token" => special_tokens.unk_token = token,
+                "cls_token" => special_tokens.cls_token = token,
+                _ => special_tokens.sep_token = token,
+            }
+        }
+    }
+    for &key in &["pad_token", "mask_token"] {
+        let token = match config.get(key) {
+            None => continue,
+            Some(&Value::Null) => None,
+            Some(value) => Some(token_content(value, file, key)?.to_string()),
+        };
+        match key {
+            "pad_token" => special_tokens.pad_token = token,
+            _ => special_tokens.mask_token = token,
+        }
+    }
+    if let Some(tokens) = config.get("additional_special_tokens") {
+        let tokens = tokens
+            .as_array()
+            .ok_or_else(|| invalid(file, "additional_special_tokens"))?;
+        special_tokens.additional_special_tokens = tokens
+            .iter()
+            .map(|token| {
+                token_content(token, file, "additional_special_tokens").map(str::to_string)
+            })
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(())
+}
+
+fn get_bool(
+    config: &Map<String, Value>,
+    file: &str,
+    key: &str,
+) -> Result<Option<bool>, Box<dyn Error>> {
+    match config.get(key) {
+        None | Some(&Value::Null) => Ok(None),
+        Some(&Value::Bool(b)) => Ok(Some(b)),
+        Some(_) => Err(invalid(file, key)),
+    }
+}
+
+impl FullTokenizer {
+    /// Loads a model directory as saved by Hugging Face `BertTokenizer`:
+    /// `vocab.txt`, plus `tokenizer_config.json`, `special_tokens_map.json`
+    /// and `added_tokens.json` when present.
+    ///
+    /// The config sets `do_lower_case`, `tokenize_chinese_chars`,
+    /// `strip_accents`, `model_max_length` and the special tokens, which
+    /// `special_tokens_map.json` overrides. Tokens of `added_tokens.json`
+    /// are appended to the vocabulary; they and all special tokens are
+    /// matched verbatim in the input, as in `transformers`.
+    pub fn from_pretrained_dir<T: AsRef<str>>(dir: T) -> Result<FullTokenizer, Box<dyn Error>> {
+        let dir = Path::new(dir.as_ref());
+        let vocab_file = dir.join("vocab.txt");
+        let vocab_file = vocab_file
+            .to_str()
+            .ok_or_else(|| format!("{}: path is not UTF-8", vocab_file.display()))?;
+        let (vocab, inv_vocab) = FullTokenizer::load_vocab(vocab_file)?;
+
+        // tokens outside vocab.txt take the ids following it, as in
+        // `transformers`
+        let mut added_tokens = Vec::new();
+        let mut added_vocab = Vocab::new();
+        if let Some(tokens) = read_json_object(dir, "added_tokens.json")? {
+            let mut tokens = tokens
+                .iter()
+                .map(|(token, id)| match id.as_u64() {
+                    Some(id) => Ok((id as usize, token.clone())),
+                    None => Err(invalid("added_tokens.json", token)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            tokens.sort();
+            for (id, token) in tokens {
+                let next_id = vocab.len() + added_vocab.len();
+                match vocab.get(&token) {
+                    Some(&vocab_id) if vocab_id == id => {}
+                    None if id == next_id => {
+                        added_vocab.insert(token.clone(), id);
+                    }
+                    _ => {
+                        return Err(format!(
+                            "added_tokens.json: token `{}` has id {}, expected {}",
+                            token,
+                            id,
+                            vocab.get(&token).cloned().unwrap_or(next_id)
+                        )
+                        .into())
+                    }
+                }
+                added_tokens.push(token);
+            }
+        }
+
+        let mut special_tokens = SpecialTokens::default();
+        let mut builder = FullTokenizerBuilder::from_vocabs(vocab, inv_vocab)
+            .added_vocab(added_vocab)
+            .optional_default_tokens(true);
+
+        if let Some(config) = read_json_object(dir, "tokenizer_config.json")? {
+            let file = "tokenizer_config.json";
+            if let Some(do_lower_case) = get_bool(&config, file, "do_lower_case")? {
+                builder = builder.do_lower_case(do_lower_case);
+            }
+            if let Some(tokenize_chinese_chars) = get_bool(&config, file, "tokenize_chinese_chars")?
+            {
+                builder = builder.tokenize_chinese_chars(tokenize_chinese_chars);
+            }
+            if let Some(strip_accents) = get_bool(&config, file, "strip_accents")? {
+                builder = builder.strip_accents(strip_accents);
+            }
+            // `transformers` stores int(1e30) when the length is unknown,
+            // which does not fit an integer and is skipped here
+            let model_max_length = config
+                .get("model_max_length")
+                .or_else(|| config.get("max_len"))
+                .and_then(Value::as_u64);
+            if let Some(model_max_length) = model_max_length {
+                builder = builder.model_max_length(model_max_length as usize);
+            }
+            apply_special_tokens(&mut special_tokens, &config, file)?;
+        }
+        if let Some(map) = read_json_object(dir, "special_tokens_map.json")? {
+            apply_special_tokens(&mut special_tokens, &map, "special_tokens_map.json")?;
+        }
+
+        for (_, token) in special_tokens.with_roles() {
+            if !added_tokens.iter().any(|added| added == token) {
+                added_tokens.push(token.to_string());
+            }
+        }
+        builder
+            .special_tokens(special_tokens)
+            .added_tokens(added_tokens)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_from_pretrained_dir() {
+        let dir = env::temp_dir().join(format!("bert_tokenizer_pretrained_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("vocab.txt"),
+            "[PAD]\n<unk>\n[CLS]\n[SEP]\n[MASK]\nwant\n##ed\nun\ncafé\ncafe\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("tokenizer_config.json"),
+            r#"{
+                "do_lower_case": true,
+                "strip_accents": false,
+                "model_max_length": 8,
+                "unk_token": "[UNK]",
+                "tokenizer_class": "BertTokenizer"
+            }"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("special_tokens_map.json"),
+            r#"{
+                "unk_token": {"content": "<unk>", "lstrip": false, "normalized": true},
+                "cls_token": "[CLS]",
+                "additional_special_tokens": ["<e>"]
+            }"#,
+        )
+        .unwrap();
+        fs::write(dir.join("added_tokens.json"), r#"{"<e>": 10}"#).unwrap();
+
+        let tokenizer = FullTokenizer::from_pretrained_dir(dir.to_str().unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tokenizer.unk_token_id(), 1);
+        assert_eq!(
+            tokenizer.special_tokens().additional_special_tokens,
+            ["<e>"]
+        );
+        assert_eq!(tokenizer.model_max_length(), Some(8));
+        assert_eq!(
+            tokenizer.tokenize("Wanted Café<e>[MASK] x"),
+            ["want", "##ed", "café", "<e>", "[MASK]", "<unk>"]
+        );
+
+        let encoding = tokenizer.encode("un <e>", 0).unwrap();
+        assert_eq!(encoding.input_ids, [2, 7, 10, 3]);
+        let encoding = tokenizer.encode("un un un un un un un", 0).unwrap();
+        assert_eq!(encoding.input_ids, [2, 7, 7, 7, 7, 7, 7, 3]);
+        assert_eq!(encoding.num_truncated_a, 1);
+        assert_eq!(tokenizer.encode("un", 16).unwrap().len(), 16);
+    }
+
+    #[test]
+    fn test_from_pretrained_dir_errors() {
+        let dir = env::temp_dir().join(format!(
+            "bert_tokenizer_pretrained_errors_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        assert!(FullTokenizer::from_pretrained_dir(dir.to_str().unwrap()).is_err());
+
+        fs::write(dir.join("vocab.txt"), "[UNK]\n[CLS]\n[SEP]\n").unwrap();
+        fs::write(dir.join("added_tokens.json"), r#"{"<e>": 7}"#).unwrap();
+        let error = FullTokenizer::from_pretrained_dir(dir.to_str().unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "added_tokens.json: token `<e>` has id 7, expected 3"
+        );
+
+        fs::write(dir.join("added_tokens.json"), r#"{"<e>": 3}"#).unwrap();
+        let tokenizer = FullTokenizer::from_pretrained_dir(dir.to_str().unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(tokenizer.pad_token_id(), None);
+        assert_eq!(tokenizer.special_tokens().mask_token, None);
+    }
+}
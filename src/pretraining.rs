@@ -0,0 +1,785 @@
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+
+use tokenization::{BasicTokenizer, FullTokenizer, Piece};
+
+/// A sentence of a document, as token ids.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sentence {
+    pub ids: Vec<i64>,
+    /// Whether each token continues the word of the token before it: word
+    /// pieces after the first, and the chars of a Chinese word after the
+    /// first. Only whole word masking looks at it.
+    pub is_continuation: Vec<bool>,
+}
+
+impl From<Vec<i64>> for Sentence {
+    fn from(ids: Vec<i64>) -> Sentence {
+        let is_continuation = vec![false; ids.len()];
+        Sentence {
+            ids,
+            is_continuation,
+        }
+    }
+}
+
+pub type Document = Vec<Sentence>;
+
+/// How the chars that `BasicTokenizer` splits Chinese text into are grouped
+/// back into words for whole word masking.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ChineseWordSegmentation {
+    /// Every char is a word of its own.
+    #[default]
+    Chars,
+    /// The longest words of a user dictionary, matched from left to right.
+    Dictionary {
+        words: HashSet<String>,
+        /// Length of the longest word, in chars.
+        max_word_len: usize,
+    },
+    /// Lines are segmented already, with whitespace between the words.
+    Presegmented,
+}
+
+impl ChineseWordSegmentation {
+    pub fn from_words<I, T>(words: I) -> ChineseWordSegmentation
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let words: HashSet<String> = words
+            .into_iter()
+            .map(|word| word.as_ref().to_string())
+            .filter(|word| !word.is_empty())
+            .collect();
+        let max_word_len = words.iter().map(|word| word.chars().count()).max();
+        ChineseWordSegmentation::Dictionary {
+            words,
+            max_word_len: max_word_len.unwrap_or(0),
+        }
+    }
+
+    /// Loads a user dictionary with one word per line. Anything after the
+    /// first whitespace of a line, like the frequencies and tags of jieba
+    /// dictionaries, is ignored.
+    pub fn load_dictionary<T: AsRef<str>>(
+        path: T,
+    ) -> Result<ChineseWordSegmentation, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(path.as_ref())?);
+        let mut words = Vec::new();
+        for line in reader.lines() {
+            if let Some(word) = line?.split_whitespace().next() {
+                words.push(word.to_string());
+            }
+        }
+        Ok(ChineseWordSegmentation::from_words(words))
+    }
+}
+
+/// One masked-LM and next-sentence-prediction example,
+/// `[CLS] A [SEP] B [SEP]` without padding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainingInstance {
+    pub input_ids: Vec<i64>,
+    pub segment_ids: Vec<i64>,
+    /// Masked positions in increasing order.
+    pub masked_lm_positions: Vec<usize>,
+    /// Original ids at `masked_lm_positions`.
+    pub masked_lm_ids: Vec<i64>,
+    /// Whether B was sampled from another document, the label of next
+    /// sentence prediction.
+    pub is_random_next: bool,
+}
+
+/// Generates BERT pretraining data, a port of `create_pretraining_data.py`
+/// of google-research/bert.
+///
+/// Every document is turned into `[CLS] A [SEP] B [SEP]` instances, where B
+/// follows A in half of the cases and comes from a random document
+/// otherwise. 15% of the tokens are then chosen for prediction; 80% of them
+/// are replaced by `[MASK]`, 10% by a random token and 10% kept. With
+/// whole word masking, the pieces of a word and the chars of a Chinese word
+/// are chosen together.
+///
+/// The output only depends on the seed, not on the number of threads.
+///
+/// ```no_run
+/// # use bert_tokenizer::{FullTokenizer, PretrainingDataGenerator};
+/// let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+/// let generator = PretrainingDataGenerator::new(&tokenizer)
+///     .max_seq_len(512)
+///     .max_predictions_per_seq(76)
+///     .seed(42);
+/// let documents = generator.read_documents(&["corpus.txt"]).unwrap();
+/// let instances = generator.generate(&documents).unwrap();
+/// ```
+pub struct PretrainingDataGenerator<'a> {
+    tokenizer: &'a FullTokenizer,
+    max_seq_len: usize,
+    dupe_factor: usize,
+    short_seq_prob: f64,
+    masked_lm_prob: f64,
+    max_predictions_per_seq: usize,
+    seed: u64,
+    whole_word_mask: bool,
+    segmentation: ChineseWordSegmentation,
+}
+
+impl<'a> PretrainingDataGenerator<'a> {
+    pub fn new(tokenizer: &'a FullTokenizer) -> PretrainingDataGenerator<'a> {
+        PretrainingDataGenerator {
+            tokenizer,
+            max_seq_len: 128,
+            dupe_factor: 10,
+            short_seq_prob: 0.1,
+            masked_lm_prob: 0.15,
+            max_predictions_per_seq: 20,
+            seed: 12345,
+            whole_word_mask: false,
+            segmentation: ChineseWordSegmentation::default(),
+        }
+    }
+
+    /// Length of the instances, special tokens included. 128 by default.
+    pub fn max_seq_len(mut self, max_seq_len: usize) -> PretrainingDataGenerator<'a> {
+        self.max_seq_len = max_seq_len;
+        self
+    }
+
+    /// Number of passes over the documents, each with different masks and
+    /// sentence pairs. 10 by default.
+    pub fn dupe_factor(mut self, dupe_factor: usize) -> PretrainingDataGenerator<'a> {
+        self.dupe_factor = dupe_factor;
+        self
+    }
+
+    /// Probability of targeting a random length shorter than `max_seq_len`,
+    /// to reduce the mismatch with short fine-tuning inputs. 0.1 by default.
+    pub fn short_seq_prob(mut self, short_seq_prob: f64) -> PretrainingDataGenerator<'a> {
+        self.short_seq_prob = short_seq_prob;
+        self
+    }
+
+    /// Fraction of the tokens chosen for prediction. 0.15 by default.
+    pub fn masked_lm_prob(mut self, masked_lm_prob: f64) -> PretrainingDataGenerator<'a> {
+        self.masked_lm_prob = masked_lm_prob;
+        self
+    }
+
+    /// 20 by default.
+    pub fn max_predictions_per_seq(
+        mut self,
+        max_predictions_per_seq: usize,
+    ) -> PretrainingDataGenerator<'a> {
+        self.max_predictions_per_seq = max_predictions_per_seq;
+        self
+    }
+
+    /// 12345 by default.
+    pub fn seed(mut self, seed: u64) -> PretrainingDataGenerator<'a> {
+        self.seed = seed;
+        self
+    }
+
+    /// Masks all the tokens of a word together, as BERT-wwm. Off by
+    /// default.
+    pub fn whole_word_mask(mut self, whole_word_mask: bool) -> PretrainingDataGenerator<'a> {
+        self.whole_word_mask = whole_word_mask;
+        self
+    }
+
+    /// Finds the Chinese words of the input, every char on its own by
+    /// default.
+    pub fn chinese_word_segmentation(
+        mut self,
+        segmentation: ChineseWordSegmentation,
+    ) -> PretrainingDataGenerator<'a> {
+        self.segmentation = segmentation;
+        self
+    }
+
+    /// Tokenizes `line` and marks the Chinese chars that continue a word.
+    fn segment(&self, line: &str) -> (Vec<Piece>, Vec<bool>) {
+        let pieces = self.tokenizer.tokenize_to_pieces(line);
+        let is_chinese: Vec<bool> = pieces
+            .iter()
+            .map(|piece| {
+                let mut chars = line[piece.byte_offsets.0..piece.byte_offsets.1].chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => BasicTokenizer::_is_chinese_char(c as u32),
+                    _ => false,
+                }
+            })
+            .collect();
+        // Chinese chars with nothing in between, which may form a word
+        let joined = |i: usize| {
+            i > 0
+                && is_chinese[i - 1]
+                && is_chinese[i]
+                && pieces[i - 1].byte_offsets.1 == pieces[i].byte_offsets.0
+        };
+
+        let mut is_continuation = vec![false; pieces.len()];
+        match self.segmentation {
+            ChineseWordSegmentation::Chars => {}
+            ChineseWordSegmentation::Presegmented => {
+                for (i, is_continuation) in is_continuation.iter_mut().enumerate() {
+                    *is_continuation = joined(i);
+                }
+            }
+            ChineseWordSegmentation::Dictionary {
+                ref words,
+                max_word_len,
+            } => {
+                let mut start = 0;
+                while start < pieces.len() {
+                    let mut end = start + 1;
+                    while end < pieces.len() && end - start < max_word_len && joined(end) {
+                        end += 1;
+                    }
+                    // longest match first, single chars are words anyway
+                    while end - start > 1 {
+                        let word =
+                            &line[pieces[start].byte_offsets.0..pieces[end - 1].byte_offsets.1];
+                        if words.contains(word) {
+                            break;
+                        }
+                        end -= 1;
+                    }
+                    for is_continuation in &mut is_continuation[start + 1..end] {
+                        *is_continuation = true;
+                    }
+                    start = end;
+                }
+            }
+        }
+        (pieces, is_continuation)
+    }
+
+    /// Tokenizes `line` into a sentence, marking the tokens that continue a
+    /// word.
+    pub fn sentence(&self, line: &str) -> Sentence {
+        let (pieces, mut is_continuation) = self.segment(line);
+        for i in 1..pieces.len() {
+            is_continuation[i] |= pieces[i].word == pieces[i - 1].word;
+        }
+        Sentence {
+            ids: pieces.iter().map(|piece| piece.id).collect(),
+            is_continuation,
+        }
+    }
+
+    /// Positions of the Chinese chars that continue a word in the encoding
+    /// `[CLS] line [SEP]`, the `ref_ids` that the Chinese BERT-wwm tooling
+    /// stores for `DataCollatorForWholeWordMask` of `transformers`.
+    pub fn chinese_ref_ids(&self, line: &str) -> Vec<usize> {
+        let (_, is_continuation) = self.segment(line);
+        (0..is_continuation.len())
+            .filter(|&i| is_continuation[i])
+            .map(|i| i + 1)
+            .collect()
+    }
+
+    /// The tokens of `line`, with the chars continuing a Chinese word
+    /// prefixed like word pieces, e.g. `["北", "##京"]`.
+    pub fn whole_word_tokens(&self, line: &str) -> Vec<String> {
+        let (pieces, is_continuation) = self.segment(line);
+        let prefix = &self.tokenizer.wordpiece_tokenizer.continuing_subword_prefix;
+        pieces
+            .iter()
+            .zip(is_continuation)
+            .map(|(piece, is_continuation)| {
+                let token = self.tokenizer.id_to_token(piece.id as usize).unwrap_or("");
+                match is_continuation {
+                    true => format!("{}{}", prefix, token),
+                    false => token.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    /// Writes the [`chinese_ref_ids`](#method.chinese_ref_ids) of every line
+    /// of `input_file` to `output_file`, one JSON list per line.
+    pub fn write_chinese_ref<T: AsRef<str>>(
+        &self,
+        input_file: T,
+        output_file: T,
+    ) -> Result<(), Box<dyn Error>> {
+        let reader = BufReader::new(File::open(input_file.as_ref())?);
+        let lines = reader.lines().collect::<Result<Vec<String>, _>>()?;
+        let ref_ids: Vec<Vec<usize>> = lines
+            .par_iter()
+            .map(|line| self.chinese_ref_ids(line))
+            .collect();
+        let mut writer = BufWriter::new(File::create(output_file.as_ref())?);
+        for ids in ref_ids {
+            writeln!(writer, "{}", serde_json::to_string(&ids)?)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads documents from text files with one sentence per line and a
+    /// blank line between documents. Lines are tokenized on the rayon
+    /// thread pool; sentences without any token are dropped.
+    ///
+    /// Every line goes through the Chinese word segmentation; with
+    /// `Presegmented`, whitespace only separates words.
+    pub fn read_documents<T: AsRef<str>>(
+        &self,
+        files: &[T],
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let mut lines = Vec::new();
+        for file in files {
+            let reader = BufReader::new(File::open(file.as_ref())?);
+            for line in reader.lines() {
+                lines.push(line?);
+            }
+            // documents never span files
+            lines.push(String::new());
+        }
+        let sentences: Vec<Option<Sentence>> = lines
+            .par_iter()
+            .map(|line| match line.trim() {
+                "" => None,
+                line => Some(self.sentence(line)),
+            })
+            .collect();
+
+        let mut documents = vec![Vec::new()];
+        for sentence in sentences {
+            match sentence {
+                None => documents.push(Vec::new()),
+                Some(ref sentence) if sentence.ids.is_empty() => {}
+                Some(sentence) => documents.last_mut().unwrap().push(sentence),
+            }
+        }
+        documents.retain(|document| !document.is_empty());
+        Ok(documents)
+    }
+
+    fn mask_token_id(&self) -> Result<i64, Box<dyn Error>> {
+        match self.tokenizer.mask_token_id() {
+            Some(id) => Ok(id as i64),
+            None => Err("the tokenizer has no mask token".into()),
+        }
+    }
+
+    /// Creates `dupe_factor` rounds of instances from `documents`, shuffled.
+    pub fn generate(
+        &self,
+        documents: &[Document],
+    ) -> Result<Vec<TrainingInstance>, Box<dyn Error>> {
+        let mask_token_id = self.mask_token_id()?;
+        if self.max_seq_len < 5 {
+            return Err(format!("max_seq_len {} is too short for a pair", self.max_seq_len).into());
+        }
+        // stream 0 shuffles, every document of every round gets its own
+        // stream so that documents can be processed in parallel
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        let mut documents: Vec<&Document> = documents
+            .iter()
+            .filter(|document| !document.is_empty())
+            .collect();
+        documents.shuffle(&mut rng);
+        let vocab_ids: Vec<i64> = self.tokenizer.vocab().map(|(_, id)| id as i64).collect();
+
+        let num_documents = documents.len();
+        let mut instances: Vec<TrainingInstance> = (0..self.dupe_factor * num_documents)
+            .into_par_iter()
+            .flat_map_iter(|stream| {
+                let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+                rng.set_stream(stream as u64 + 1);
+                let index = stream % num_documents;
+                let masking = (mask_token_id, &vocab_ids[..]);
+                self.create_instances(&documents, index, masking, &mut rng)
+            })
+            .collect();
+        instances.shuffle(&mut rng);
+        Ok(instances)
+    }
+
+    /// `create_instances_from_document`: packs consecutive sentences of
+    /// `documents[index]` into instances of about the target length.
+    fn create_instances(
+        &self,
+        documents: &[&Document],
+        index: usize,
+        masking: (i64, &[i64]),
+        rng: &mut ChaCha8Rng,
+    ) -> Vec<TrainingInstance> {
+        let document = documents[index];
+        // [CLS], [SEP], [SEP]
+        let max_num_tokens = self.max_seq_len - 3;
+        // usually fill the whole sequence, but sometimes aim shorter
+        let target_seq_len = if rng.gen::<f64>() < self.short_seq_prob {
+            rng.gen_range(2..=max_num_tokens)
+        } else {
+            max_num_tokens
+        };
+
+        let mut instances = Vec::new();
+        let mut current_chunk: Vec<&Sentence> = Vec::new();
+        let mut current_len = 0;
+        let mut i = 0;
+        while i < document.len() {
+            current_chunk.push(&document[i]);
+            current_len += document[i].ids.len();
+            if i == document.len() - 1 || current_len >= target_seq_len {
+                // sentences of the chunk that go into A
+                let a_end = if current_chunk.len() >= 2 {
+                    rng.gen_range(1..current_chunk.len())
+                } else {
+                    1
+                };
+                let mut tokens_a = concat(&current_chunk[..a_end]);
+                let mut tokens_b = Vec::new();
+                let is_random_next = current_chunk.len() == 1 || rng.gen::<f64>() < 0.5;
+                if is_random_next {
+                    let target_b_len = target_seq_len.saturating_sub(tokens_a.len());
+                    // a large corpus rarely draws the same document, a tiny
+                    // one may only have this one
+                    let mut random_index = 0;
+                    for _ in 0..10 {
+                        random_index = rng.gen_range(0..documents.len());
+                        if random_index != index {
+                            break;
+                        }
+                    }
+                    let random_document = documents[random_index];
+                    let random_start = rng.gen_range(0..random_document.len());
+                    for sentence in &random_document[random_start..] {
+                        tokens_b
+                            .extend(sentence.ids.iter().cloned().zip(&sentence.is_continuation));
+                        if tokens_b.len() >= target_b_len {
+                            break;
+                        }
+                    }
+                    // the unused sentences go into the next chunk
+                    i -= current_chunk.len() - a_end;
+                } else {
+                    tokens_b = concat(&current_chunk[a_end..]);
+                }
+                truncate_seq_pair(&mut tokens_a, &mut tokens_b, max_num_tokens, rng);
+
+                let cls_token_id = self.tokenizer.cls_token_id() as i64;
+                let sep_token_id = self.tokenizer.sep_token_id() as i64;
+                let mut tokens = Vec::with_capacity(tokens_a.len() + tokens_b.len() + 3);
+                tokens.push((cls_token_id, &false));
+                tokens.extend_from_slice(&tokens_a);
+                tokens.push((sep_token_id, &false));
+                tokens.extend_from_slice(&tokens_b);
+                tokens.push((sep_token_id, &false));
+                let (mut input_ids, is_continuation): (Vec<i64>, Vec<bool>) =
+                    tokens.into_iter().map(|(id, &c)| (id, c)).unzip();
+                let mut segment_ids = vec![0; tokens_a.len() + 2];
+                segment_ids.resize(input_ids.len(), 1);
+
+                let (masked_lm_positions, masked_lm_ids) =
+                    self.mask(&mut input_ids, &is_continuation, masking, rng);
+                instances.push(TrainingInstance {
+                    input_ids,
+                    segment_ids,
+                    masked_lm_positions,
+                    masked_lm_ids,
+                    is_random_next,
+                });
+                current_chunk.clear();
+                current_len = 0;
+            }
+            i += 1;
+        }
+        instances
+    }
+
+    /// `create_masked_lm_predictions`: chooses the positions to predict,
+    /// masks `input_ids` in place and returns the positions with their
+    /// original ids. With whole word masking, the tokens of a word are
+    /// chosen together.
+    fn mask(
+        &self,
+        input_ids: &mut [i64],
+        is_continuation: &[bool],
+        (mask_token_id, vocab_ids): (i64, &[i64]),
+        rng: &mut ChaCha8Rng,
+    ) -> (Vec<usize>, Vec<i64>) {
+        let cls_token_id = self.tokenizer.cls_token_id() as i64;
+        let sep_token_id = self.tokenizer.sep_token_id() as i64;
+        let mut candidates: Vec<Vec<usize>> = Vec::new();
+        for i in 0..input_ids.len() {
+            if input_ids[i] == cls_token_id || input_ids[i] == sep_token_id {
+                continue;
+            }
+            match candidates.last_mut() {
+                // a word never continues across [SEP]
+                Some(word)
+                    if self.whole_word_mask
+                        && is_continuation[i]
+                        && word.last() == Some(&(i - 1)) =>
+                {
+                    word.push(i)
+                }
+                _ => candidates.push(vec![i]),
+            }
+        }
+        candidates.shuffle(rng);
+        let num_to_predict = ((input_ids.len() as f64 * self.masked_lm_prob).round() as usize)
+            .max(1)
+            .min(self.max_predictions_per_seq);
+
+        let mut masked_lms = Vec::with_capacity(num_to_predict);
+        for word in candidates {
+            if masked_lms.len() >= num_to_predict {
+                break;
+            }
+            // skip words that would exceed the budget
+            if masked_lms.len() + word.len() > num_to_predict {
+                continue;
+            }
+            for position in word {
+                masked_lms.push((position, input_ids[position]));
+                if rng.gen::<f64>() < 0.8 {
+                    input_ids[position] = mask_token_id;
+                } else if rng.gen::<f64>() < 0.5 {
+                    // 10% of the time, keep the original token
+                } else {
+                    // 10% of the time, replace with a random token
+                    input_ids[position] = vocab_ids[rng.gen_range(0..vocab_ids.len())];
+                }
+            }
+        }
+        masked_lms.sort_unstable();
+        masked_lms.into_iter().unzip()
+    }
+}
+
+/// The tokens of consecutive sentences with their continuation marks.
+fn concat<'s>(sentences: &[&'s Sentence]) -> Vec<(i64, &'s bool)> {
+    sentences
+        .iter()
+        .flat_map(|sentence| sentence.ids.iter().cloned().zip(&sentence.is_continuation))
+        .collect()
+}
+
+/// Truncates the longer sequence until the pair fits, dropping from its
+/// front or back at random so the model does not learn where text was cut.
+fn truncate_seq_pair<T>(
+    tokens_a: &mut Vec<T>,
+    tokens_b: &mut Vec<T>,
+    max_num_tokens: usize,
+    rng: &mut ChaCha8Rng,
+) {
+    while tokens_a.len() + tokens_b.len() > max_num_tokens {
+        let longer = if tokens_a.len() > tokens_b.len() {
+            &mut *tokens_a
+        } else {
+            &mut *tokens_b
+        };
+        if rng.gen::<f64>() < 0.5 {
+            longer.remove(0);
+        } else {
+            longer.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Documents whose token ids encode the document and the position in
+    /// it: document `d` holds `10000 + 1000 * d, 10000 + 1000 * d + 1, ...`.
+    /// Words start at the ids divisible by `word_len`.
+    fn documents(word_len: i64) -> Vec<Document> {
+        (0..6)
+            .map(|d| {
+                let mut next = 10000 + 1000 * d;
+                (0..20)
+                    .map(|s| {
+                        let len = 3 + (s * 7 + d as usize) % 9;
+                        let ids: Vec<i64> = (next..next + len as i64).collect();
+                        next += len as i64;
+                        Sentence {
+                            is_continuation: ids.iter().map(|id| id % word_len != 0).collect(),
+                            ids,
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Undoes the masking of an instance.
+    fn original_ids(instance: &TrainingInstance) -> Vec<i64> {
+        let mut ids = instance.input_ids.clone();
+        for (&position, &id) in instance
+            .masked_lm_positions
+            .iter()
+            .zip(&instance.masked_lm_ids)
+        {
+            ids[position] = id;
+        }
+        ids
+    }
+
+    #[test]
+    fn test_generate() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let generator = PretrainingDataGenerator::new(&tokenizer)
+            .max_seq_len(32)
+            .max_predictions_per_seq(4)
+            .dupe_factor(5);
+        let instances = generator.generate(&documents(1)).unwrap();
+        assert_eq!(instances, generator.generate(&documents(1)).unwrap());
+        assert_ne!(
+            instances,
+            generator.seed(1).generate(&documents(1)).unwrap()
+        );
+
+        let (cls, sep, mask) = (101, 102, 103);
+        let (mut num_masked, mut num_mask_token) = (0, 0);
+        let mut num_random_next = 0;
+        for instance in &instances {
+            let input_ids = &instance.input_ids;
+            let len = input_ids.len();
+            assert!(len <= 32);
+            assert_eq!(input_ids[0], cls);
+            assert_eq!(input_ids[len - 1], sep);
+            let a_len = input_ids.iter().position(|&id| id == sep).unwrap() - 1;
+            assert!(a_len >= 1 && a_len + 3 < len);
+            let mut segment_ids = vec![0; a_len + 2];
+            segment_ids.resize(len, 1);
+            assert_eq!(instance.segment_ids, segment_ids);
+
+            let positions = &instance.masked_lm_positions;
+            let expected = ((len as f64 * 0.15).round() as usize).clamp(1, 4);
+            assert_eq!(positions.len(), expected);
+            assert_eq!(instance.masked_lm_ids.len(), expected);
+            assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+            for &position in positions {
+                assert!(position != 0 && position != a_len + 1 && position != len - 1);
+                num_masked += 1;
+                num_mask_token += (input_ids[position] == mask) as usize;
+            }
+            let original = original_ids(instance);
+
+            let tokens_a = &original[1..a_len + 1];
+            let tokens_b = &original[a_len + 2..len - 1];
+            let document = |ids: &[i64]| {
+                assert!(ids.windows(2).all(|pair| pair[1] == pair[0] + 1));
+                (ids[0] - 10000) / 1000
+            };
+            if instance.is_random_next {
+                num_random_next += 1;
+            } else {
+                assert_eq!(document(tokens_a), document(tokens_b));
+                assert!(tokens_a[a_len - 1] < tokens_b[0]);
+            }
+        }
+        let num_masked = num_masked as f64;
+        assert!((0.7..0.9).contains(&(num_mask_token as f64 / num_masked)));
+        let random_next = num_random_next as f64 / instances.len() as f64;
+        assert!((0.3..0.7).contains(&random_next));
+    }
+
+    #[test]
+    fn test_read_documents() {
+        let path =
+            std::env::temp_dir().join(format!("bert_tokenizer_corpus_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "hello world\n你好\n\n\n  \nhello\n").unwrap();
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let generator = PretrainingDataGenerator::new(&tokenizer);
+        let documents = generator.read_documents(&[path, path]).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let documents: Vec<Vec<Vec<i64>>> = documents
+            .iter()
+            .map(|document| {
+                document
+                    .iter()
+                    .map(|sentence| sentence.ids.clone())
+                    .collect()
+            })
+            .collect();
+        let first = vec![vec![8701, 8572], vec![872, 1962]];
+        assert_eq!(
+            documents,
+            [first.clone(), vec![vec![8701]], first, vec![vec![8701]]]
+        );
+    }
+
+    #[test]
+    fn test_chinese_word_segmentation() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("bert_tokenizer_dict_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "喜欢 3 v\n北京 5 ns\n天安门 2 ns\n北京天安门广场\n").unwrap();
+        let dictionary = ChineseWordSegmentation::load_dictionary(path).unwrap();
+        let generator =
+            PretrainingDataGenerator::new(&tokenizer).chinese_word_segmentation(dictionary);
+
+        let line = "我喜欢北京天安门。Hello 北京";
+        assert_eq!(
+            generator.whole_word_tokens(line),
+            [
+                "我", "喜", "##欢", "北", "##京", "天", "##安", "##门", "。", "hello", "北", "##京"
+            ]
+        );
+        assert_eq!(generator.chinese_ref_ids(line), [3, 5, 7, 8, 12]);
+        let sentence = generator.sentence("我喜欢 playing");
+        assert_eq!(sentence.ids, tokenizer.tokenize_to_ids("我喜欢 playing"));
+        assert_eq!(sentence.is_continuation, [false, false, true, false, true]);
+
+        let ref_path = format!("{}.ref", path);
+        std::fs::write(path, format!("{}\n\n你好\n", line)).unwrap();
+        generator.write_chinese_ref(path, &ref_path).unwrap();
+        let ref_ids = std::fs::read_to_string(&ref_path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(&ref_path).unwrap();
+        assert_eq!(ref_ids, "[3,5,7,8,12]\n[]\n[]\n");
+
+        let generator = PretrainingDataGenerator::new(&tokenizer)
+            .chinese_word_segmentation(ChineseWordSegmentation::Presegmented);
+        assert_eq!(
+            generator.chinese_ref_ids("我 喜欢 北京 天安门 。 Hello 北京"),
+            [3, 5, 7, 8, 12]
+        );
+        let generator = PretrainingDataGenerator::new(&tokenizer);
+        assert!(generator.chinese_ref_ids(line).is_empty());
+    }
+
+    #[test]
+    fn test_whole_word_mask() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let generator = PretrainingDataGenerator::new(&tokenizer)
+            .max_seq_len(32)
+            .max_predictions_per_seq(6)
+            .dupe_factor(5)
+            .whole_word_mask(true);
+        let instances = generator.generate(&documents(3)).unwrap();
+        let mut masked_words = 0;
+        for instance in &instances {
+            let ids = original_ids(instance);
+            let masked = &instance.masked_lm_positions;
+            assert!(masked.len() <= 6);
+            // neighbours inside a word are masked together
+            for i in 2..ids.len() {
+                let same_word = ids[i] % 3 != 0 && ids[i] == ids[i - 1] + 1;
+                if same_word {
+                    assert_eq!(masked.contains(&i), masked.contains(&(i - 1)));
+                    masked_words += masked.contains(&i) as usize;
+                }
+            }
+        }
+        assert!(masked_words > 0);
+    }
+}
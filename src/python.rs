@@ -0,0 +1,223 @@
+//! Native Python module `bert_tokenizer._native`, built with the `python`
+//! feature.
+
+use numpy::{IntoPyArray, PyArray1, PyArray2, PyArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyType;
+
+use super::{Encoding, FullTokenizer};
+use tokenization::{InvVocab, Vocab};
+
+pyo3::create_exception!(_native, TokenizerError, PyValueError);
+
+fn tokenizer_error<E: ToString>(e: E) -> PyErr {
+    TokenizerError::new_err(e.to_string())
+}
+
+type Arrays1<'py> = (
+    Bound<'py, PyArray1<i64>>,
+    Bound<'py, PyArray1<i64>>,
+    Bound<'py, PyArray1<i64>>,
+);
+type Arrays2<'py> = (
+    Bound<'py, PyArray2<i64>>,
+    Bound<'py, PyArray2<i64>>,
+    Bound<'py, PyArray2<i64>>,
+);
+
+/// A vocabulary file, or the tokens of a vocabulary in id order.
+#[derive(FromPyObject)]
+enum VocabArg {
+    File(String),
+    Tokens(Vec<String>),
+}
+
+#[pyclass(name = "FullTokenizer", module = "bert_tokenizer._native", frozen)]
+struct PyFullTokenizer {
+    tokenizer: FullTokenizer,
+    do_lower_case: bool,
+}
+
+/// Moves the arrays of `encoding` into numpy arrays, without copying.
+fn into_arrays(py: Python, encoding: Encoding) -> Arrays1 {
+    (
+        encoding.input_ids.into_pyarray(py),
+        encoding.input_mask.into_pyarray(py),
+        encoding.segment_ids.into_pyarray(py),
+    )
+}
+
+fn into_array2<'py>(
+    py: Python<'py>,
+    rows: Vec<i64>,
+    seq_len: usize,
+) -> PyResult<Bound<'py, PyArray2<i64>>> {
+    let batch_size = rows.len().checked_div(seq_len).unwrap_or(0);
+    rows.into_pyarray(py).reshape([batch_size, seq_len])
+}
+
+#[pymethods]
+impl PyFullTokenizer {
+    #[new]
+    #[pyo3(signature = (vocab, do_lower_case = true))]
+    fn new(vocab: VocabArg, do_lower_case: bool) -> PyResult<PyFullTokenizer> {
+        let tokenizer = match vocab {
+            VocabArg::File(vocab_file) => FullTokenizer::new(vocab_file, do_lower_case),
+            VocabArg::Tokens(tokens) => {
+                let vocab: Vocab = tokens
+                    .iter()
+                    .enumerate()
+                    .map(|(id, token)| (token.clone(), id))
+                    .collect();
+                let inv_vocab: InvVocab = tokens.into_iter().enumerate().collect();
+                FullTokenizer::from_vocabs(vocab, inv_vocab, do_lower_case)
+            }
+        }
+        .map_err(tokenizer_error)?;
+        Ok(PyFullTokenizer {
+            tokenizer,
+            do_lower_case,
+        })
+    }
+
+    /// Pickles the vocabulary itself, so unpickling does not depend on the
+    /// vocabulary file. Tokens are listed by id, as a repeated line of the
+    /// file keeps its own id.
+    fn __reduce__<'py>(
+        slf: &Bound<'py, PyFullTokenizer>,
+    ) -> (Bound<'py, PyType>, (Vec<String>, bool)) {
+        let this = slf.get();
+        let tokens = this
+            .tokenizer
+            .wordpiece_tokenizer
+            .inv_vocab
+            .values()
+            .chain(this.tokenizer.inv_added_vocab.values())
+            .cloned()
+            .collect();
+        (slf.get_type(), (tokens, this.do_lower_case))
+    }
+
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        self.tokenizer.tokenize(text)
+    }
+
+    /// Encodes `text_a`, or the pair `text_a`, `text_b`, into numpy arrays
+    /// `(input_ids, input_mask, segment_ids)`.
+    #[pyo3(signature = (text_a, text_b = None, max_seq_len = 0))]
+    fn encode<'py>(
+        &self,
+        py: Python<'py>,
+        text_a: &str,
+        text_b: Option<&str>,
+        max_seq_len: usize,
+    ) -> PyResult<Arrays1<'py>> {
+        let encoding = match text_b {
+            Some(text_b) => self.tokenizer.encode_pair(text_a, text_b, max_seq_len),
+            None => self.tokenizer.encode(text_a, max_seq_len),
+        }
+        .map_err(tokenizer_error)?;
+        Ok(into_arrays(py, encoding))
+    }
+
+    /// Same as the ctypes wrapper this module replaces: an empty `text_b`
+    /// encodes `text_a` alone.
+    fn convert_pairs<'py>(
+        &self,
+        py: Python<'py>,
+        text_a: &str,
+        text_b: &str,
+        max_seq_len: usize,
+    ) -> PyResult<Arrays1<'py>> {
+        let text_b = if text_b.is_empty() {
+            None
+        } else {
+            Some(text_b)
+        };
+        self.encode(py, text_a, text_b, max_seq_len)
+    }
+
+    /// Encodes a batch on all cores without holding the GIL, into
+    /// `[batch, seq_len]` numpy arrays. Rows are padded to the longest one,
+    /// which is `max_seq_len` unless it is 0.
+    #[pyo3(signature = (texts_a, texts_b = None, max_seq_len = 0))]
+    fn encode_batch<'py>(
+        &self,
+        py: Python<'py>,
+        texts_a: Vec<String>,
+        texts_b: Option<Vec<String>>,
+        max_seq_len: usize,
+    ) -> PyResult<Arrays2<'py>> {
+        let tokenizer = &self.tokenizer;
+        let (input_ids, input_mask, segment_ids, seq_len) = py
+            .detach(|| {
+                let mut encodings = match texts_b {
+                    Some(ref texts_b) => {
+                        if texts_b.len() != texts_a.len() {
+                            return Err(format!(
+                                "{} texts_a but {} texts_b",
+                                texts_a.len(),
+                                texts_b.len()
+                            )
+                            .into());
+                        }
+                        let pairs: Vec<(&str, &str)> = texts_a
+                            .iter()
+                            .zip(texts_b)
+                            .map(|(text_a, text_b)| (text_a.as_str(), text_b.as_str()))
+                            .collect();
+                        tokenizer.encode_pair_batch(&pairs, max_seq_len)?
+                    }
+                    None => tokenizer.encode_batch(&texts_a, max_seq_len)?,
+                };
+                let seq_len = encodings.iter().map(Encoding::len).max().unwrap_or(0);
+                let mut input_ids = Vec::with_capacity(encodings.len() * seq_len);
+                let mut input_mask = Vec::with_capacity(encodings.len() * seq_len);
+                let mut segment_ids = Vec::with_capacity(encodings.len() * seq_len);
+                for encoding in &mut encodings {
+                    tokenizer.pad_encoding(encoding, seq_len);
+                    input_ids.extend_from_slice(&encoding.input_ids);
+                    input_mask.extend_from_slice(&encoding.input_mask);
+                    segment_ids.extend_from_slice(&encoding.segment_ids);
+                }
+                Ok((input_ids, input_mask, segment_ids, seq_len))
+            })
+            .map_err(|e: Box<dyn std::error::Error + Send + Sync>| tokenizer_error(e))?;
+        Ok((
+            into_array2(py, input_ids, seq_len)?,
+            into_array2(py, input_mask, seq_len)?,
+            into_array2(py, segment_ids, seq_len)?,
+        ))
+    }
+
+    #[pyo3(signature = (ids, skip_special_tokens = true, clean_up_spaces = true))]
+    fn decode(&self, ids: Vec<i64>, skip_special_tokens: bool, clean_up_spaces: bool) -> String {
+        self.tokenizer
+            .decode(&ids, skip_special_tokens, clean_up_spaces)
+    }
+
+    fn token_to_id(&self, token: &str) -> Option<usize> {
+        self.tokenizer.token_to_id(token)
+    }
+
+    fn id_to_token(&self, id: usize) -> Option<&str> {
+        self.tokenizer.id_to_token(id)
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.tokenizer.vocab_size()
+    }
+
+    fn __len__(&self) -> usize {
+        self.tokenizer.vocab_size()
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "_native")]
+fn native(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_class::<PyFullTokenizer>()?;
+    m.add("TokenizerError", m.py().get_type::<TokenizerError>())?;
+    Ok(())
+}
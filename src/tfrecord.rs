@@ -0,0 +1,594 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+
+use encoding::Encoding;
+use pretraining::TrainingInstance;
+
+/// One feature of a `tf.train.Example`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Feature {
+    BytesList(Vec<Vec<u8>>),
+    FloatList(Vec<f32>),
+    Int64List(Vec<i64>),
+}
+
+/// A `tf.train.Example`, the record type TensorFlow input pipelines parse
+/// with `tf.io.parse_single_example`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Example {
+    /// Features by name, serialized in name order.
+    pub features: BTreeMap<String, Feature>,
+}
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+/// CRC-32C (Castagnoli), the checksum of TFRecord files.
+fn crc32c(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        CRC32C_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// The CRC as TFRecord stores it, rotated and offset so that CRCs of data
+/// containing CRCs stay well distributed.
+fn masked_crc(data: &[u8]) -> u32 {
+    let crc = crc32c(data);
+    crc.rotate_right(15).wrapping_add(0xa282_ead8)
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Writes a length-delimited field.
+fn write_bytes_field(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    write_varint(buf, (field << 3 | 2) as u64);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn read_varint(buf: &mut &[u8]) -> Result<u64, Box<dyn Error>> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first().ok_or("truncated varint")?;
+        *buf = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return Ok(value);
+        }
+    }
+    Err("varint longer than 10 bytes".into())
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], Box<dyn Error>> {
+    if buf.len() < len {
+        return Err("truncated field".into());
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+/// A field value of the protobuf wire format.
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32([u8; 4]),
+}
+
+/// Splits a protobuf message into its `(field number, value)` pairs.
+fn read_fields(mut buf: &[u8]) -> Result<Vec<(u64, WireValue<'_>)>, Box<dyn Error>> {
+    let mut fields = Vec::new();
+    while !buf.is_empty() {
+        let key = read_varint(&mut buf)?;
+        let value = match key & 7 {
+            0 => WireValue::Varint(read_varint(&mut buf)?),
+            1 => {
+                take(&mut buf, 8)?;
+                WireValue::Fixed64
+            }
+            2 => {
+                let len = read_varint(&mut buf)? as usize;
+                WireValue::Bytes(take(&mut buf, len)?)
+            }
+            5 => {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(take(&mut buf, 4)?);
+                WireValue::Fixed32(bytes)
+            }
+            wire_type => return Err(format!("unsupported wire type {}", wire_type).into()),
+        };
+        fields.push((key >> 3, value));
+    }
+    Ok(fields)
+}
+
+impl Feature {
+    fn to_bytes(&self) -> Vec<u8> {
+        // lists are packed, as TensorFlow writes them
+        let mut list = Vec::new();
+        let field = match *self {
+            Feature::BytesList(ref values) => {
+                for value in values {
+                    write_bytes_field(&mut list, 1, value);
+                }
+                1
+            }
+            Feature::FloatList(ref values) => {
+                let mut packed = Vec::with_capacity(values.len() * 4);
+                for value in values {
+                    packed.extend_from_slice(&value.to_le_bytes());
+                }
+                if !packed.is_empty() {
+                    write_bytes_field(&mut list, 1, &packed);
+                }
+                2
+            }
+            Feature::Int64List(ref values) => {
+                let mut packed = Vec::with_capacity(values.len());
+                for &value in values {
+                    write_varint(&mut packed, value as u64);
+                }
+                if !packed.is_empty() {
+                    write_bytes_field(&mut list, 1, &packed);
+                }
+                3
+            }
+        };
+        let mut buf = Vec::with_capacity(list.len() + 2);
+        write_bytes_field(&mut buf, field, &list);
+        buf
+    }
+
+    /// Reads packed and unpacked lists alike.
+    fn from_bytes(buf: &[u8]) -> Result<Feature, Box<dyn Error>> {
+        let mut feature = None;
+        for (field, value) in read_fields(buf)? {
+            let list = match (field, value) {
+                (1, WireValue::Bytes(list))
+                | (2, WireValue::Bytes(list))
+                | (3, WireValue::Bytes(list)) => list,
+                _ => continue,
+            };
+            let mut values = match field {
+                1 => Feature::BytesList(Vec::new()),
+                2 => Feature::FloatList(Vec::new()),
+                _ => Feature::Int64List(Vec::new()),
+            };
+            for (_, value) in read_fields(list)?
+                .into_iter()
+                .filter(|&(field, _)| field == 1)
+            {
+                match (&mut values, value) {
+                    (&mut Feature::BytesList(ref mut values), WireValue::Bytes(bytes)) => {
+                        values.push(bytes.to_vec())
+                    }
+                    (&mut Feature::FloatList(ref mut values), WireValue::Fixed32(bytes)) => {
+                        values.push(f32::from_le_bytes(bytes))
+                    }
+                    (&mut Feature::FloatList(ref mut values), WireValue::Bytes(mut packed)) => {
+                        if packed.len() % 4 != 0 {
+                            return Err("packed floats are not a multiple of 4 bytes".into());
+                        }
+                        while !packed.is_empty() {
+                            let mut bytes = [0; 4];
+                            bytes.copy_from_slice(take(&mut packed, 4)?);
+                            values.push(f32::from_le_bytes(bytes));
+                        }
+                    }
+                    (&mut Feature::Int64List(ref mut values), WireValue::Varint(value)) => {
+                        values.push(value as i64)
+                    }
+                    (&mut Feature::Int64List(ref mut values), WireValue::Bytes(mut packed)) => {
+                        while !packed.is_empty() {
+                            values.push(read_varint(&mut packed)? as i64);
+                        }
+                    }
+                    _ => return Err("invalid feature list".into()),
+                }
+            }
+            feature = Some(values);
+        }
+        feature.ok_or_else(|| "feature without a list".into())
+    }
+}
+
+fn padded(values: &[i64], len: usize) -> Feature {
+    let mut list = values.to_vec();
+    list.resize(len, 0);
+    Feature::Int64List(list)
+}
+
+impl Example {
+    pub fn new() -> Example {
+        Example::default()
+    }
+
+    /// The `input_ids`, `input_mask` and `segment_ids` of an encoding, as
+    /// the fine-tuning scripts of google-research/bert read them; padding is
+    /// left to the tokenizer.
+    pub fn from_encoding(encoding: &Encoding) -> Example {
+        let mut example = Example::new();
+        for &(name, values) in &[
+            ("input_ids", &encoding.input_ids),
+            ("input_mask", &encoding.input_mask),
+            ("segment_ids", &encoding.segment_ids),
+        ] {
+            example
+                .features
+                .insert(name.to_string(), Feature::Int64List(values.clone()));
+        }
+        example
+    }
+
+    /// The features of a pretraining instance as `create_pretraining_data.py`
+    /// writes them: `input_ids`, `input_mask` and `segment_ids` zero-padded
+    /// to `max_seq_len`, `masked_lm_positions`, `masked_lm_ids` and
+    /// `masked_lm_weights` zero-padded to `max_predictions_per_seq`, and
+    /// `next_sentence_labels`, 1 for a random next sentence.
+    pub fn from_instance(
+        instance: &TrainingInstance,
+        max_seq_len: usize,
+        max_predictions_per_seq: usize,
+    ) -> Result<Example, Box<dyn Error>> {
+        let num_tokens = instance.input_ids.len();
+        if num_tokens > max_seq_len {
+            return Err(format!(
+                "instance of {} tokens exceeds the max_seq_len {}",
+                num_tokens, max_seq_len
+            )
+            .into());
+        }
+        let num_predictions = instance.masked_lm_positions.len();
+        if num_predictions > max_predictions_per_seq {
+            return Err(format!(
+                "instance of {} predictions exceeds the max_predictions_per_seq {}",
+                num_predictions, max_predictions_per_seq
+            )
+            .into());
+        }
+
+        let positions: Vec<i64> = instance
+            .masked_lm_positions
+            .iter()
+            .map(|&position| position as i64)
+            .collect();
+        let mut weights = vec![1.0; num_predictions];
+        weights.resize(max_predictions_per_seq, 0.0);
+
+        let mut example = Example::new();
+        let features = &mut example.features;
+        features.insert(
+            "input_ids".to_string(),
+            padded(&instance.input_ids, max_seq_len),
+        );
+        features.insert(
+            "input_mask".to_string(),
+            Feature::Int64List((0..max_seq_len).map(|i| (i < num_tokens) as i64).collect()),
+        );
+        features.insert(
+            "segment_ids".to_string(),
+            padded(&instance.segment_ids, max_seq_len),
+        );
+        features.insert(
+            "masked_lm_positions".to_string(),
+            padded(&positions, max_predictions_per_seq),
+        );
+        features.insert(
+            "masked_lm_ids".to_string(),
+            padded(&instance.masked_lm_ids, max_predictions_per_seq),
+        );
+        features.insert("masked_lm_weights".to_string(), Feature::FloatList(weights));
+        features.insert(
+            "next_sentence_labels".to_string(),
+            Feature::Int64List(vec![instance.is_random_next as i64]),
+        );
+        Ok(example)
+    }
+
+    /// Serializes the example in the protobuf wire format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut features = Vec::new();
+        for (name, feature) in &self.features {
+            let mut entry = Vec::new();
+            write_bytes_field(&mut entry, 1, name.as_bytes());
+            write_bytes_field(&mut entry, 2, &feature.to_bytes());
+            write_bytes_field(&mut features, 1, &entry);
+        }
+        let mut buf = Vec::with_capacity(features.len() + 4);
+        write_bytes_field(&mut buf, 1, &features);
+        buf
+    }
+
+    /// Parses a serialized `tf.train.Example`, skipping unknown fields.
+    pub fn from_bytes(buf: &[u8]) -> Result<Example, Box<dyn Error>> {
+        let mut example = Example::new();
+        for (field, value) in read_fields(buf)? {
+            let features = match (field, value) {
+                (1, WireValue::Bytes(features)) => features,
+                _ => continue,
+            };
+            for (field, value) in read_fields(features)? {
+                let entry = match (field, value) {
+                    (1, WireValue::Bytes(entry)) => entry,
+                    _ => continue,
+                };
+                let (mut name, mut feature) = (String::new(), None);
+                for (field, value) in read_fields(entry)? {
+                    match (field, value) {
+                        (1, WireValue::Bytes(bytes)) => name = String::from_utf8(bytes.to_vec())?,
+                        (2, WireValue::Bytes(bytes)) => feature = Some(Feature::from_bytes(bytes)?),
+                        _ => {}
+                    }
+                }
+                let feature = feature.ok_or_else(|| format!("feature `{}` has no value", name))?;
+                example.features.insert(name, feature);
+            }
+        }
+        Ok(example)
+    }
+}
+
+/// Writes records in the TFRecord format: each record is framed by its
+/// length and the masked CRC-32C of the length and of the data.
+///
+/// ```no_run
+/// # use bert_tokenizer::{Example, FullTokenizer, TfRecordWriter};
+/// let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+/// let mut writer = TfRecordWriter::create("train.tfrecord").unwrap();
+/// for encoding in tokenizer.encode_batch(&["今天天气很好"], 128).unwrap() {
+///     writer.write_example(&Example::from_encoding(&encoding)).unwrap();
+/// }
+/// writer.flush().unwrap();
+/// ```
+pub struct TfRecordWriter<W: Write> {
+    writer: W,
+}
+
+impl TfRecordWriter<BufWriter<File>> {
+    pub fn create<T: AsRef<str>>(path: T) -> Result<Self, Box<dyn Error>> {
+        Ok(TfRecordWriter::new(BufWriter::new(File::create(
+            path.as_ref(),
+        )?)))
+    }
+}
+
+impl<W: Write> TfRecordWriter<W> {
+    pub fn new(writer: W) -> TfRecordWriter<W> {
+        TfRecordWriter { writer }
+    }
+
+    pub fn write_record(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let len = (data.len() as u64).to_le_bytes();
+        self.writer.write_all(&len)?;
+        self.writer.write_all(&masked_crc(&len).to_le_bytes())?;
+        self.writer.write_all(data)?;
+        self.writer.write_all(&masked_crc(data).to_le_bytes())?;
+        Ok(())
+    }
+
+    pub fn write_example(&mut self, example: &Example) -> Result<(), Box<dyn Error>> {
+        self.write_record(&example.to_bytes())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads the records of a TFRecord file, verifying their CRCs.
+pub struct TfRecordReader<R: Read> {
+    reader: R,
+}
+
+impl TfRecordReader<BufReader<File>> {
+    pub fn open<T: AsRef<str>>(path: T) -> Result<Self, Box<dyn Error>> {
+        Ok(TfRecordReader::new(BufReader::new(File::open(
+            path.as_ref(),
+        )?)))
+    }
+}
+
+impl<R: Read> TfRecordReader<R> {
+    pub fn new(reader: R) -> TfRecordReader<R> {
+        TfRecordReader { reader }
+    }
+
+    fn read_crc(&mut self, data: &[u8], what: &str) -> Result<(), Box<dyn Error>> {
+        let mut crc = [0; 4];
+        self.reader.read_exact(&mut crc)?;
+        if u32::from_le_bytes(crc) != masked_crc(data) {
+            return Err(format!("corrupted record: {} CRC mismatch", what).into());
+        }
+        Ok(())
+    }
+
+    /// Returns `None` at the end of the input.
+    pub fn read_record(&mut self) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let mut len = [0; 8];
+        let mut read = 0;
+        while read < len.len() {
+            match self.reader.read(&mut len[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err("truncated record".into()),
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.read_crc(&len, "length")?;
+        let mut data = vec![0; u64::from_le_bytes(len) as usize];
+        self.reader.read_exact(&mut data)?;
+        self.read_crc(&data, "data")?;
+        Ok(Some(data))
+    }
+
+    pub fn read_example(&mut self) -> Result<Option<Example>, Box<dyn Error>> {
+        match self.read_record()? {
+            Some(data) => Ok(Some(Example::from_bytes(&data)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokenization::FullTokenizer;
+
+    #[test]
+    fn test_crc() {
+        assert_eq!(crc32c(b""), 0);
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+        assert_eq!(crc32c(&[0; 32]), 0x8a91_36aa);
+    }
+
+    #[test]
+    fn test_example() {
+        let mut example = Example::new();
+        example
+            .features
+            .insert("a".to_string(), Feature::Int64List(vec![1]));
+        // tf.train.Example(features=tf.train.Features(feature={
+        //     "a": tf.train.Feature(int64_list=tf.train.Int64List(value=[1]))
+        // })).SerializeToString()
+        assert_eq!(
+            example.to_bytes(),
+            b"\x0a\x0c\x0a\x0a\x0a\x01a\x12\x05\x1a\x03\x0a\x01\x01"
+        );
+
+        example.features.insert(
+            "bytes".to_string(),
+            Feature::BytesList(vec![b"".to_vec(), "你好".as_bytes().to_vec()]),
+        );
+        example
+            .features
+            .insert("floats".to_string(), Feature::FloatList(vec![1.0, -0.5]));
+        example.features.insert(
+            "ints".to_string(),
+            Feature::Int64List(vec![0, -1, 300, i64::MAX]),
+        );
+        example
+            .features
+            .insert("empty".to_string(), Feature::Int64List(vec![]));
+        assert_eq!(Example::from_bytes(&example.to_bytes()).unwrap(), example);
+
+        // unpacked lists and unknown fields
+        let unpacked = b"\x0a\x15\x0a\x13\x0a\x01f\x12\x0c\x12\x0a\x0d\x00\x00\x80\x3f\x0d\x00\x00\x00\xbf\x10\x01";
+        let example = Example::from_bytes(unpacked).unwrap();
+        assert_eq!(example.features["f"], Feature::FloatList(vec![1.0, -0.5]));
+        assert!(Example::from_bytes(&unpacked[..10]).is_err());
+    }
+
+    #[test]
+    fn test_tfrecord_round_trip() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let encodings = tokenizer
+            .encode_pair_batch(&[("hello", "world"), ("你好", "")], 8)
+            .unwrap();
+        let instance = TrainingInstance {
+            input_ids: vec![101, 103, 8572, 102, 872, 102],
+            segment_ids: vec![0, 0, 0, 0, 1, 1],
+            masked_lm_positions: vec![1, 4],
+            masked_lm_ids: vec![8701, 872],
+            is_random_next: true,
+        };
+
+        let mut writer = TfRecordWriter::new(Vec::new());
+        for encoding in &encodings {
+            writer
+                .write_example(&Example::from_encoding(encoding))
+                .unwrap();
+        }
+        let example = Example::from_instance(&instance, 8, 3).unwrap();
+        writer.write_example(&example).unwrap();
+        let data = writer.into_inner();
+
+        let mut reader = TfRecordReader::new(&data[..]);
+        for encoding in &encodings {
+            let read = reader.read_example().unwrap().unwrap();
+            assert_eq!(read, Example::from_encoding(encoding));
+            assert_eq!(
+                read.features["input_ids"],
+                Feature::Int64List(encoding.input_ids.clone())
+            );
+        }
+        let read = reader.read_example().unwrap().unwrap();
+        assert!(reader.read_record().unwrap().is_none());
+        assert_eq!(read, example);
+        let features = &read.features;
+        assert_eq!(
+            features["input_ids"],
+            Feature::Int64List(vec![101, 103, 8572, 102, 872, 102, 0, 0])
+        );
+        assert_eq!(
+            features["input_mask"],
+            Feature::Int64List(vec![1, 1, 1, 1, 1, 1, 0, 0])
+        );
+        assert_eq!(
+            features["segment_ids"],
+            Feature::Int64List(vec![0, 0, 0, 0, 1, 1, 0, 0])
+        );
+        assert_eq!(
+            features["masked_lm_positions"],
+            Feature::Int64List(vec![1, 4, 0])
+        );
+        assert_eq!(
+            features["masked_lm_ids"],
+            Feature::Int64List(vec![8701, 872, 0])
+        );
+        assert_eq!(
+            features["masked_lm_weights"],
+            Feature::FloatList(vec![1.0, 1.0, 0.0])
+        );
+        assert_eq!(
+            features["next_sentence_labels"],
+            Feature::Int64List(vec![1])
+        );
+
+        assert!(Example::from_instance(&instance, 5, 3).is_err());
+        assert!(Example::from_instance(&instance, 8, 1).is_err());
+
+        let mut corrupted = data.clone();
+        corrupted[20] ^= 1;
+        assert!(TfRecordReader::new(&corrupted[..]).read_record().is_err());
+        let truncated = &data[..data.len() - 1];
+        let mut reader = TfRecordReader::new(truncated);
+        reader.read_record().unwrap();
+        reader.read_record().unwrap();
+        assert!(reader.read_record().is_err());
+    }
+}
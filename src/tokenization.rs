@@ -1,4 +1,5 @@
 use indexmap::IndexMap;
+use rayon::prelude::*;
 use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
@@ -6,143 +7,243 @@ use std::io::BufReader;
 use unicode_categories::UnicodeCategories;
 use unicode_normalization::UnicodeNormalization;
 
-use super::{INPUT_IDS, INPUT_MASK, SEGMENT_IDS};
+use super::{Encoding, FullTokenizerBuilder, PaddingParams, SpecialTokens, TruncationStrategy};
+use trie::WordpieceTrie;
+use truncation::truncate_seq_pair;
+
+pub type Vocab = IndexMap<String, usize>;
+pub type InvVocab = IndexMap<usize, String>;
 
 pub struct BasicTokenizer {
     pub do_lower_case: bool,
+    pub strip_accents: bool,
+    pub tokenize_chinese_chars: bool,
 }
 
 const WHITESPACE_CHARACTER: char = b' ' as char;
 
 fn _is_punctuation(character: char) -> bool {
     let cp = character as u32;
-    if cp >= 33 && cp <= 47
-        || cp >= 58 && cp <= 64
-        || cp >= 91 && cp <= 96
-        || cp >= 123 && cp <= 126
+    if (33..=47).contains(&cp)
+        || (58..=64).contains(&cp)
+        || (91..=96).contains(&cp)
+        || (123..=126).contains(&cp)
     {
         return true;
     }
     character.is_punctuation()
 }
 
+/// Chinese chars plus CJK and full-width punctuation, which are written
+/// without spaces in between.
+fn _is_cjk(character: char) -> bool {
+    let cp = character as u32;
+    BasicTokenizer::_is_chinese_char(cp)
+        || (0x3000..=0x303F).contains(&cp)
+        || (0xFF00..=0xFFEF).contains(&cp)
+}
+
+/// Punctuation that attaches to the preceding word when decoding.
+fn _is_closing_punctuation(character: char) -> bool {
+    match character {
+        '.' | ',' | '!' | '?' | ';' | ':' | '%' | ')' | ']' | '}' => true,
+        _ => _is_cjk(character) && _is_punctuation(character),
+    }
+}
+
 fn _is_control(character: char) -> bool {
     if character == '\t' || character == '\n' || character == '\r' {
         return false;
     }
-    return character.is_control();
+    character.is_control()
+}
+
+/// A token together with the span of the original text it was produced from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub value: String,
+    /// `(start, end)` char offsets into the original text.
+    pub offsets: (usize, usize),
+    /// `(start, end)` UTF-8 byte offsets into the original text.
+    pub byte_offsets: (usize, usize),
+}
+
+/// Normalized chars, each paired with the index of the char of the original
+/// text it was produced from.
+type AlignedChars = Vec<(char, usize)>;
+
+/// Maps char offsets of `text` to byte offsets; has one extra entry for the
+/// end of the text.
+fn char_to_byte_offsets(text: &str) -> Vec<usize> {
+    text.char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .collect()
 }
 
 impl BasicTokenizer {
+    /// Accents are stripped exactly when lowercasing, and Chinese chars are
+    /// split off, as in the original BERT tokenizer.
     pub fn new(do_lower_case: bool) -> BasicTokenizer {
-        BasicTokenizer { do_lower_case }
+        BasicTokenizer {
+            do_lower_case,
+            strip_accents: do_lower_case,
+            tokenize_chinese_chars: true,
+        }
     }
-    fn _clean_text<T: AsRef<str>>(&self, text: T) -> Vec<char> {
-        let text = text.as_ref();
+    fn _clean_text(&self, text: &str) -> AlignedChars {
         let mut output = Vec::new();
-        for character in text.chars() {
+        for (i, character) in text.chars().enumerate() {
             let char_value = character as u32;
             if char_value == 0 || char_value == 0xffd || _is_control(character) {
                 continue;
             }
             if character.is_whitespace() {
-                output.push(WHITESPACE_CHARACTER);
+                output.push((WHITESPACE_CHARACTER, i));
             } else {
-                output.push(character);
+                output.push((character, i));
             }
         }
         output
     }
 
-    fn _is_chinese_char(cp: u32) -> bool {
-        return (cp >= 0x4E00 && cp <= 0x9FFF)
-            || (cp >= 0x3400 && cp <= 0x4DBF)
-            || (cp >= 0x20000 && cp <= 0x2A6DF)
-            || (cp >= 0x2A700 && cp <= 0x2B73F)
-            || (cp >= 0x2B740 && cp <= 0x2B81F)
-            || (cp >= 0x2B820 && cp <= 0x2CEAF)
-            || (cp >= 0xF900 && cp <= 0xFAFF)
-            || (cp >= 0x2F800 && cp <= 0x2FA1F);
+    pub(crate) fn _is_chinese_char(cp: u32) -> bool {
+        (0x4E00..=0x9FFF).contains(&cp)
+            || (0x3400..=0x4DBF).contains(&cp)
+            || (0x20000..=0x2A6DF).contains(&cp)
+            || (0x2A700..=0x2B73F).contains(&cp)
+            || (0x2B740..=0x2B81F).contains(&cp)
+            || (0x2B820..=0x2CEAF).contains(&cp)
+            || (0xF900..=0xFAFF).contains(&cp)
+            || (0x2F800..=0x2FA1F).contains(&cp)
     }
 
-    fn _tokenize_chinese_chars(&self, text: Vec<char>) -> Vec<char> {
+    fn _tokenize_chinese_chars(&self, text: AlignedChars) -> AlignedChars {
         let mut output = Vec::new();
-        for character in text {
+        for (character, i) in text {
             let cp = character as u32;
             if BasicTokenizer::_is_chinese_char(cp) {
-                output.push(WHITESPACE_CHARACTER);
-                output.push(character);
-                output.push(WHITESPACE_CHARACTER);
+                output.push((WHITESPACE_CHARACTER, i));
+                output.push((character, i));
+                output.push((WHITESPACE_CHARACTER, i));
             } else {
-                output.push(character);
+                output.push((character, i));
             }
         }
         output
     }
 
-    fn _run_strip_accents<T: AsRef<str>>(text: T) -> String {
-        let text = text.as_ref();
-        text.nfd().filter(|c| !c.is_mark_nonspacing()).collect()
+    fn _run_lower_case(text: AlignedChars) -> AlignedChars {
+        // `str::to_lowercase` handles the final sigma, which per-char
+        // lowercasing does not; every char still maps to the same number of
+        // lowercase chars, so the alignment can be carried over.
+        let text_str: String = text.iter().map(|&(c, _)| c).collect();
+        let lowered = text_str.to_lowercase();
+        let mut lowered = lowered.chars();
+        let mut output = Vec::with_capacity(text.len());
+        for (character, i) in text {
+            for _ in character.to_lowercase() {
+                output.push((lowered.next().unwrap(), i));
+            }
+        }
+        output
+    }
+
+    fn _run_strip_accents(text: AlignedChars) -> AlignedChars {
+        let mut output = Vec::with_capacity(text.len());
+        for (character, i) in text {
+            for c in std::iter::once(character).nfd() {
+                if !c.is_mark_nonspacing() {
+                    output.push((c, i));
+                }
+            }
+        }
+        output
     }
 
-    fn _run_split_on_punc<T: AsRef<str>>(text: T) -> Vec<String> {
-        let chars: Vec<char> = text.as_ref().chars().collect();
-        let mut i = 0;
-        let mut output: Vec<Vec<char>> = Vec::new();
+    fn _run_split_on_punc(text: AlignedChars) -> Vec<AlignedChars> {
+        let mut output: Vec<AlignedChars> = Vec::new();
         let mut start_new_word = true;
-        while i < chars.len() {
-            let char_ = chars[i];
+        for (char_, i) in text {
             if _is_punctuation(char_) {
-                output.push(vec![char_]);
+                output.push(vec![(char_, i)]);
                 start_new_word = true;
             } else {
                 if start_new_word {
-                    output.push(vec![char_]);
+                    output.push(vec![(char_, i)]);
                 } else {
-                    output.last_mut().unwrap().push(char_);
+                    output.last_mut().unwrap().push((char_, i));
                 }
                 start_new_word = false;
             }
-            i += 1;
         }
-        output.iter().map(|v| v.iter().collect()).collect()
+        output
     }
 
-    pub fn tokenize<T>(&self, text: T) -> Vec<String>
-    where
-        T: AsRef<str>,
-    {
-        let text = text.as_ref();
+    fn tokenize_aligned(&self, text: &str) -> Vec<AlignedChars> {
         let text = self._clean_text(text);
 
-        let text = self._tokenize_chinese_chars(text);
-
-        let process_str: String = text.iter().collect();
+        let text = if self.tokenize_chinese_chars {
+            self._tokenize_chinese_chars(text)
+        } else {
+            text
+        };
 
-        let tokens: Vec<String> = process_str
-            .split_whitespace()
+        text.split(|&(c, _)| c.is_whitespace())
+            .filter(|s| !s.is_empty())
             .map(|s| {
+                let mut token = s.to_vec();
                 if self.do_lower_case {
-                    BasicTokenizer::_run_strip_accents(s.to_lowercase())
-                } else {
-                    s.to_string()
+                    token = BasicTokenizer::_run_lower_case(token);
+                }
+                if self.strip_accents {
+                    token = BasicTokenizer::_run_strip_accents(token);
                 }
+                token
             })
-            .flat_map(|c| BasicTokenizer::_run_split_on_punc(c))
-            //.map(|p| p.to_string()).collect::<Vec<String>>()
-            .filter(|s| s.len() > 0)
-            .collect();
+            .flat_map(BasicTokenizer::_run_split_on_punc)
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
 
-        tokens
+    pub fn tokenize<T>(&self, text: T) -> Vec<String>
+    where
+        T: AsRef<str>,
+    {
+        self.tokenize_aligned(text.as_ref())
+            .iter()
+            .map(|token| token.iter().map(|&(c, _)| c).collect())
+            .collect()
+    }
+
+    /// Same as [`tokenize`](#method.tokenize), but every token also records
+    /// the span of `text` it covers. Offsets survive lowercasing, accent
+    /// stripping and the removal of control chars.
+    pub fn tokenize_with_offsets<T: AsRef<str>>(&self, text: T) -> Vec<Token> {
+        let text = text.as_ref();
+        let byte_offsets = char_to_byte_offsets(text);
+        self.tokenize_aligned(text)
+            .iter()
+            .map(|token| {
+                let start = token[0].1;
+                let end = token[token.len() - 1].1 + 1;
+                Token {
+                    value: token.iter().map(|&(c, _)| c).collect(),
+                    offsets: (start, end),
+                    byte_offsets: (byte_offsets[start], byte_offsets[end]),
+                }
+            })
+            .collect()
     }
 }
 
-struct WordpieceTokenizer {
-    vocab: IndexMap<String, usize>,
-    inv_vocab: IndexMap<usize, String>,
-    unk_token: String,
+pub(crate) struct WordpieceTokenizer {
+    pub(crate) vocab: IndexMap<String, usize>,
+    pub(crate) inv_vocab: IndexMap<usize, String>,
+    trie: WordpieceTrie,
     unk_id: usize,
-    max_input_chars_per_word: usize,
+    pub(crate) max_input_chars_per_word: usize,
+    pub(crate) continuing_subword_prefix: String,
 }
 
 impl WordpieceTokenizer {
@@ -151,124 +252,107 @@ impl WordpieceTokenizer {
         inv_vocab: IndexMap<usize, String>,
         unk_token: T,
         max_input_chars_per_word: usize,
+        continuing_subword_prefix: T,
     ) -> WordpieceTokenizer {
         let unk_id = vocab[unk_token.as_ref()];
+        let continuing_subword_prefix = continuing_subword_prefix.as_ref().to_string();
+        let trie = WordpieceTrie::new(&vocab, &continuing_subword_prefix);
         // RFC 1682
         WordpieceTokenizer {
             vocab,
             inv_vocab,
+            trie,
             unk_id,
-            unk_token: unk_token.as_ref().to_string(),
             max_input_chars_per_word,
+            continuing_subword_prefix,
         }
     }
 
-    pub fn tokenize<T: AsRef<str>>(&self, text: T) -> Vec<String> {
-        let mut output_tokens = Vec::new();
-        for token in text.as_ref().split_whitespace() {
-            let chars: Vec<char> = token.chars().collect();
-            if chars.len() > self.max_input_chars_per_word {
-                output_tokens.push(self.unk_token.clone());
-                continue;
-            }
-            let mut is_bad = false;
-            let mut start = 0;
-            let mut sub_tokens = Vec::new();
-            while start < chars.len() {
-                let mut end = chars.len();
-                let mut cur_substr = String::new();
-                while start < end {
-                    let mut substr: String = chars[start..end].iter().collect();
-                    if start > 0 {
-                        substr = "##".to_string() + &substr;
-                    }
-                    if self.vocab.contains_key(&substr) {
-                        cur_substr = substr;
-                        break;
-                    }
-                    end -= 1;
-                }
-                if cur_substr.len() == 0 {
-                    is_bad = true;
-                    break;
-                }
-                sub_tokens.push(cur_substr);
-                start = end;
-            }
-            if is_bad {
-                output_tokens.push(self.unk_token.clone());
-            } else {
-                output_tokens.append(&mut sub_tokens);
-            }
+    /// Splits a single word with greedy longest-match-first, in time linear in
+    /// the length of the word (see `WordpieceTrie`), returning the
+    /// `(id, start, end)` char span of every piece. A word that cannot be
+    /// split, or is longer than `max_input_chars_per_word`, becomes one
+    /// unknown token covering the whole word.
+    fn tokenize_word(&self, chars: &[char]) -> Vec<(usize, usize, usize)> {
+        if chars.len() > self.max_input_chars_per_word {
+            return vec![(self.unk_id, 0, chars.len())];
+        }
+        match self.trie.tokenize_word(chars) {
+            Some(sub_tokens) => sub_tokens,
+            None => vec![(self.unk_id, 0, chars.len())],
         }
-        return output_tokens;
     }
 
-    pub fn tokenize_to_ids<T: AsRef<str>>(&self, text: T) -> Vec<i64> {
+    /// Word pieces of whitespace-separated text; `FullTokenizer` goes
+    /// through `tokenize_word` directly to keep offsets.
+    #[cfg(test)]
+    pub fn tokenize<T: AsRef<str>>(&self, text: T) -> Vec<String> {
         let mut output_tokens = Vec::new();
-
         for token in text.as_ref().split_whitespace() {
             let chars: Vec<char> = token.chars().collect();
-            if chars.len() > self.max_input_chars_per_word {
-                output_tokens.push(self.unk_id as i64);
-                continue;
-            }
-            let mut is_bad = false;
-            let mut start = 0;
-            let mut sub_tokens = Vec::new();
-            while start < chars.len() {
-                let mut end = chars.len();
-                let mut cur_substr = -1;
-                while start < end {
-                    let mut substr: String = chars[start..end].iter().collect();
-                    if start > 0 {
-                        substr = "##".to_string() + &substr;
-                    }
-                    if let Some(val) = self.vocab.get(&substr) {
-                        cur_substr = *val as i64;
-                        break;
-                    }
-                    end -= 1;
-                }
-                if cur_substr == -1 {
-                    is_bad = true;
-                    break;
-                }
-                sub_tokens.push(cur_substr);
-                start = end;
-            }
-            if is_bad {
-                output_tokens.push(self.unk_id as i64);
-            } else {
-                output_tokens.append(&mut sub_tokens);
+            for (id, _, _) in self.tokenize_word(&chars) {
+                output_tokens.push(self.inv_vocab[&id].clone());
             }
         }
-        return output_tokens;
+        output_tokens
     }
 }
 
+/// A word piece produced by `FullTokenizer`, with its offsets into the
+/// original text and the index of the word it belongs to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Piece {
+    pub(crate) id: i64,
+    pub(crate) offsets: (usize, usize),
+    pub(crate) byte_offsets: (usize, usize),
+    pub(crate) word: usize,
+}
+
 pub struct FullTokenizer {
-    basic_tokenizer: BasicTokenizer,
-    wordpiece_tokenizer: WordpieceTokenizer,
-    cls_token_id: usize,
-    sep_token_id: usize,
+    pub(crate) basic_tokenizer: BasicTokenizer,
+    pub(crate) wordpiece_tokenizer: WordpieceTokenizer,
+    pub(crate) special_tokens: SpecialTokens,
+    pub(crate) cls_token_id: usize,
+    pub(crate) sep_token_id: usize,
+    pub(crate) pad_token_id: Option<usize>,
+    pub(crate) mask_token_id: Option<usize>,
+    /// `[CLS]`, `[SEP]`, padding and the additional special tokens, which
+    /// `decode` can leave out.
+    pub(crate) special_token_ids: Vec<usize>,
+    pub(crate) truncation_strategy: TruncationStrategy,
+    pub(crate) padding: PaddingParams,
+    /// Tokens matched verbatim in the input before basic tokenization,
+    /// longest first.
+    pub(crate) added_tokens: Vec<(String, usize)>,
+    /// Added tokens outside the WordPiece vocabulary, which only verbatim
+    /// matching produces.
+    pub(crate) added_vocab: Vocab,
+    pub(crate) inv_added_vocab: InvVocab,
+    pub(crate) model_max_length: Option<usize>,
 }
 
-pub fn convert_tokens_to_ids(vocab: &IndexMap<String, usize>, tokens: &[String]) -> Vec<usize> {
-    tokens
-        .iter()
-        .map(|k| vocab.get(k).unwrap().clone())
-        .collect()
+/// Looks up the id of every token; tokens outside `vocab` give `None`.
+#[deprecated(note = "use `FullTokenizer::convert_tokens_to_ids` or `token_to_id`")]
+pub fn convert_tokens_to_ids(
+    vocab: &IndexMap<String, usize>,
+    tokens: &[String],
+) -> Vec<Option<usize>> {
+    tokens.iter().map(|k| vocab.get(k).cloned()).collect()
 }
 
-pub fn convert_ids_to_tokens(vocab: &IndexMap<usize, String>, ids: &[usize]) -> Vec<String> {
-    ids.iter().map(|i| vocab.get(i).unwrap().clone()).collect()
+/// Looks up the token of every id; ids outside `vocab` give `None`.
+#[deprecated(note = "use `FullTokenizer::convert_ids_to_tokens` or `id_to_token`")]
+pub fn convert_ids_to_tokens(
+    vocab: &IndexMap<usize, String>,
+    ids: &[usize],
+) -> Vec<Option<String>> {
+    ids.iter().map(|i| vocab.get(i).cloned()).collect()
 }
 
 impl FullTokenizer {
-    fn load_vocab<T: AsRef<str>>(
+    pub(crate) fn load_vocab<T: AsRef<str>>(
         vocab_file: T,
-    ) -> Result<(IndexMap<String, usize>, IndexMap<usize, String>), Box<dyn Error>> {
+    ) -> Result<(Vocab, InvVocab), Box<dyn Error>> {
         let file = File::open(vocab_file.as_ref())?;
         let mut reader = BufReader::with_capacity(4096, file);
         let mut buffer: Vec<u8> = Vec::with_capacity(1024);
@@ -289,148 +373,572 @@ impl FullTokenizer {
         Ok((vocab, inv_vocab))
     }
 
+    /// Loads `vocab_file` with the default special token names. Use
+    /// `FullTokenizerBuilder` for anything else.
     pub fn new<T: AsRef<str>>(
         vocab_file: T,
         do_lower_case: bool,
     ) -> Result<FullTokenizer, Box<dyn Error>> {
-        let basic_tokenizer = BasicTokenizer::new(do_lower_case);
-
         let (vocab, inv_vocab) = FullTokenizer::load_vocab(vocab_file)?;
+        FullTokenizer::from_vocabs(vocab, inv_vocab, do_lower_case)
+    }
 
-        if !vocab.contains_key("[CLS]")
-            || !vocab.contains_key("[SEP]")
-            || !vocab.contains_key("[UNK]")
-        {
-            return Err("`[CLS]` or `[SEP]` or `[UNK]` not in vocab".into());
-        }
+    /// Same as [`new`](#method.new) for an already loaded vocabulary.
+    pub(crate) fn from_vocabs(
+        vocab: Vocab,
+        inv_vocab: InvVocab,
+        do_lower_case: bool,
+    ) -> Result<FullTokenizer, Box<dyn Error>> {
+        // only `[CLS]`, `[SEP]` and `[UNK]` have ever been required here
+        FullTokenizerBuilder::from_vocabs(vocab, inv_vocab)
+            .do_lower_case(do_lower_case)
+            .optional_default_tokens(true)
+            .build()
+    }
+
+    pub fn builder<T: AsRef<str>>(vocab_file: T) -> FullTokenizerBuilder {
+        FullTokenizerBuilder::new(vocab_file)
+    }
+
+    pub fn special_tokens(&self) -> &SpecialTokens {
+        &self.special_tokens
+    }
+
+    pub fn unk_token_id(&self) -> usize {
+        self.wordpiece_tokenizer.unk_id
+    }
+
+    pub fn token_to_id(&self, token: &str) -> Option<usize> {
+        self.wordpiece_tokenizer
+            .vocab
+            .get(token)
+            .or_else(|| self.added_vocab.get(token))
+            .cloned()
+    }
+
+    pub fn id_to_token(&self, id: usize) -> Option<&str> {
+        self.wordpiece_tokenizer
+            .inv_vocab
+            .get(&id)
+            .or_else(|| self.inv_added_vocab.get(&id))
+            .map(String::as_str)
+    }
+
+    /// Token of an id produced by this tokenizer.
+    fn token(&self, id: usize) -> &str {
+        self.id_to_token(id).expect("id outside the vocabulary")
+    }
+
+    /// Number of tokens, including the added tokens outside the WordPiece
+    /// vocabulary.
+    pub fn vocab_size(&self) -> usize {
+        self.wordpiece_tokenizer.vocab.len() + self.added_vocab.len()
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.token_to_id(token).is_some()
+    }
+
+    /// Every token with its id, in vocabulary file order, followed by the
+    /// added tokens outside the WordPiece vocabulary.
+    pub fn vocab(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.wordpiece_tokenizer
+            .vocab
+            .iter()
+            .chain(self.added_vocab.iter())
+            .map(|(token, &id)| (token.as_str(), id))
+    }
+
+    pub fn cls_token_id(&self) -> usize {
+        self.cls_token_id
+    }
+
+    pub fn sep_token_id(&self) -> usize {
+        self.sep_token_id
+    }
+
+    /// Id of the pad token, if one is configured. Padding falls back to id 0
+    /// without one.
+    pub fn pad_token_id(&self) -> Option<usize> {
+        self.pad_token_id
+    }
+
+    pub fn mask_token_id(&self) -> Option<usize> {
+        self.mask_token_id
+    }
+
+    pub fn truncation_strategy(&self) -> TruncationStrategy {
+        self.truncation_strategy
+    }
+
+    pub fn set_truncation_strategy(&mut self, strategy: TruncationStrategy) {
+        self.truncation_strategy = strategy;
+    }
+
+    pub fn padding(&self) -> &PaddingParams {
+        &self.padding
+    }
 
-        let cls_token_id = *vocab.get("[CLS]").unwrap();
-        let sep_token_id = *vocab.get("[SEP]").unwrap();
-        let wordpiece_tokenizer = WordpieceTokenizer::new(vocab, inv_vocab, "[UNK]", 100);
+    pub fn set_padding(&mut self, padding: PaddingParams) {
+        self.padding = padding;
+    }
 
-        Ok(FullTokenizer {
-            basic_tokenizer,
-            wordpiece_tokenizer,
-            cls_token_id,
-            sep_token_id,
-        })
+    /// Longest sequence the model accepts, if known.
+    pub fn model_max_length(&self) -> Option<usize> {
+        self.model_max_length
     }
 
     pub fn tokenize<T: AsRef<str>>(&self, text: T) -> Vec<String> {
-        let mut split_tokens = Vec::new();
-        for token in self.basic_tokenizer.tokenize(text) {
-            split_tokens.append(&mut self.wordpiece_tokenizer.tokenize(token));
+        self.tokenize_to_pieces(text.as_ref())
+            .iter()
+            .map(|piece| self.token(piece.id as usize).to_string())
+            .collect()
+    }
+
+    /// Splits `text` into `(start, end, id)` char spans, where `id` is set
+    /// for the added tokens found in it, leftmost and longest first.
+    fn split_added_tokens(&self, text: &str) -> Vec<(usize, usize, Option<usize>)> {
+        let mut spans = Vec::new();
+        let mut start = 0;
+        let mut char_idx = 0;
+        let mut byte_idx = 0;
+        while byte_idx < text.len() {
+            let rest = &text[byte_idx..];
+            let added_token = self
+                .added_tokens
+                .iter()
+                .find(|&(token, _)| rest.starts_with(token.as_str()));
+            if let Some((token, id)) = added_token {
+                let id = *id;
+                if start < char_idx {
+                    spans.push((start, char_idx, None));
+                }
+                let len = token.chars().count();
+                spans.push((char_idx, char_idx + len, Some(id)));
+                char_idx += len;
+                byte_idx += token.len();
+                start = char_idx;
+            } else {
+                char_idx += 1;
+                byte_idx += rest.chars().next().map_or(1, char::len_utf8);
+            }
         }
-        split_tokens
+        if start < char_idx {
+            spans.push((start, char_idx, None));
+        }
+        spans
     }
 
-    pub fn tokenize_to_ids<T: AsRef<str>>(&self, text: T) -> Vec<i64> {
-        let mut split_tokens = Vec::new();
-        for token in self.basic_tokenizer.tokenize(text) {
-            split_tokens.append(&mut self.wordpiece_tokenizer.tokenize_to_ids(token));
+    pub(crate) fn tokenize_to_pieces(&self, text: &str) -> Vec<Piece> {
+        let byte_offsets = char_to_byte_offsets(text);
+        let mut pieces = Vec::new();
+        let mut word = 0;
+        for (span_start, span_end, added_token) in self.split_added_tokens(text) {
+            if let Some(id) = added_token {
+                pieces.push(Piece {
+                    id: id as i64,
+                    offsets: (span_start, span_end),
+                    byte_offsets: (byte_offsets[span_start], byte_offsets[span_end]),
+                    word,
+                });
+                word += 1;
+                continue;
+            }
+            let span = &text[byte_offsets[span_start]..byte_offsets[span_end]];
+            for token in self.basic_tokenizer.tokenize_aligned(span) {
+                let chars: Vec<char> = token.iter().map(|&(c, _)| c).collect();
+                for (id, start, end) in self.wordpiece_tokenizer.tokenize_word(&chars) {
+                    let start = span_start + token[start].1;
+                    let end = span_start + token[end - 1].1 + 1;
+                    pieces.push(Piece {
+                        id: id as i64,
+                        offsets: (start, end),
+                        byte_offsets: (byte_offsets[start], byte_offsets[end]),
+                        word,
+                    });
+                }
+                word += 1;
+            }
+        }
+        pieces
+    }
+
+    /// Tokenizes every word of pre-tokenized input on its own. Pieces record
+    /// the index of their word and offsets into it.
+    fn tokenize_words_to_pieces<T: AsRef<str>>(&self, words: &[T]) -> Vec<Piece> {
+        let mut pieces = Vec::new();
+        for (word, text) in words.iter().enumerate() {
+            pieces.extend(
+                self.tokenize_to_pieces(text.as_ref())
+                    .into_iter()
+                    .map(|piece| Piece { word, ..piece }),
+            );
         }
-        split_tokens
+        pieces
+    }
+
+    pub fn tokenize_to_ids<T: AsRef<str>>(&self, text: T) -> Vec<i64> {
+        self.tokenize_to_pieces(text.as_ref())
+            .iter()
+            .map(|piece| piece.id)
+            .collect()
+    }
+
+    /// Same as [`tokenize`](#method.tokenize), but every word piece also
+    /// records the span of `text` it was produced from.
+    pub fn tokenize_with_offsets<T: AsRef<str>>(&self, text: T) -> Vec<Token> {
+        self.tokenize_to_pieces(text.as_ref())
+            .iter()
+            .map(|piece| Token {
+                value: self.token(piece.id as usize).to_string(),
+                offsets: piece.offsets,
+                byte_offsets: piece.byte_offsets,
+            })
+            .collect()
     }
 
+    /// Looks up the id of every token; tokens outside the vocabulary map to
+    /// the unknown token.
     pub fn convert_tokens_to_ids(&self, tokens: &[String]) -> Vec<usize> {
-        convert_tokens_to_ids(&self.wordpiece_tokenizer.vocab, tokens)
+        let unk_id = self.wordpiece_tokenizer.unk_id;
+        tokens
+            .iter()
+            .map(|token| self.token_to_id(token).unwrap_or(unk_id))
+            .collect()
     }
 
+    /// Looks up the token of every id; ids outside the vocabulary map to the
+    /// unknown token.
     pub fn convert_ids_to_tokens(&self, ids: &[usize]) -> Vec<String> {
-        convert_ids_to_tokens(&self.wordpiece_tokenizer.inv_vocab, ids)
+        ids.iter()
+            .map(|&id| {
+                self.id_to_token(id)
+                    .unwrap_or(&self.special_tokens.unk_token)
+                    .to_string()
+            })
+            .collect()
     }
 
-    fn truncate_seq_pair<T>(tokens_a: &mut Vec<T>, tokens_b: &mut Vec<T>, max_length: usize) {
-        loop {
-            if tokens_a.len() + tokens_b.len() <= max_length {
-                break;
+    /// Turns ids back into text, merging continuation pieces into the
+    /// preceding token.
+    ///
+    /// `skip_special_tokens` drops `[CLS]`, `[SEP]`, padding and the
+    /// additional special tokens.
+    /// `clean_up_spaces` leaves out the spaces between CJK chars and before
+    /// closing punctuation, which the tokenizer cannot tell apart from the
+    /// ones of the original text.
+    pub fn decode(&self, ids: &[i64], skip_special_tokens: bool, clean_up_spaces: bool) -> String {
+        let unk_token = self.token(self.wordpiece_tokenizer.unk_id);
+        let mut text = String::new();
+        for &id in ids {
+            let id = id as usize;
+            if skip_special_tokens && self.special_token_ids.contains(&id) {
+                continue;
             }
-            if tokens_a.len() > tokens_b.len() {
-                tokens_a.pop();
-            } else {
-                tokens_b.pop();
+            let token = self.id_to_token(id).unwrap_or(unk_token);
+            let prefix = &self.wordpiece_tokenizer.continuing_subword_prefix;
+            if token.len() > prefix.len() && token.starts_with(prefix.as_str()) {
+                text.push_str(&token[prefix.len()..]);
+                continue;
+            }
+            if let (Some(last), Some(first)) = (text.chars().last(), token.chars().next()) {
+                let glued = clean_up_spaces
+                    && (_is_closing_punctuation(first) || _is_cjk(last) && _is_cjk(first));
+                if !glued {
+                    text.push(WHITESPACE_CHARACTER);
+                }
             }
+            text.push_str(token);
         }
+        text
     }
 
-    pub fn convert_pairs<T: AsRef<str>>(
+    /// Decodes every id sequence with [`decode`](#method.decode) on the rayon
+    /// thread pool.
+    pub fn decode_batch<T: AsRef<[i64]> + Sync>(
         &self,
-        text_a: T,
-        text_b: T,
+        sequences: &[T],
+        skip_special_tokens: bool,
+        clean_up_spaces: bool,
+    ) -> Vec<String> {
+        sequences
+            .par_iter()
+            .map(|ids| self.decode(ids.as_ref(), skip_special_tokens, clean_up_spaces))
+            .collect()
+    }
+
+    fn build_encoding(
+        &self,
+        mut tokens_a: Vec<Piece>,
+        tokens_b: Option<Vec<Piece>>,
         max_seq_len: usize,
-        is_pair: bool,
-    ) -> usize {
-        let mut tokens_a = self.tokenize_to_ids(text_a);
-        let mut tokens_b = self.tokenize_to_ids(text_b);
-        let pair = is_pair as usize;
-        let added_tokens = 2 + pair;
-        let max_seq_len = {
-            if max_seq_len == 0 {
-                tokens_a.len() + tokens_b.len() + added_tokens
-            } else {
-                max_seq_len
+    ) -> Result<Encoding, Box<dyn Error + Send + Sync>> {
+        let is_pair = tokens_b.is_some();
+        let mut tokens_b = tokens_b.unwrap_or_default();
+        let added_tokens = 2 + is_pair as usize;
+        let max_seq_len = match max_seq_len {
+            // the natural length, truncated to what the model accepts
+            0 => {
+                let len = tokens_a.len() + tokens_b.len() + added_tokens;
+                self.model_max_length
+                    .map_or(len, |max_len| len.min(max_len))
             }
+            max_seq_len => max_seq_len,
         };
+        if max_seq_len < added_tokens {
+            return Err(format!(
+                "max_seq_len {} leaves no room for the {} special tokens",
+                max_seq_len, added_tokens
+            )
+            .into());
+        }
 
-        Self::truncate_seq_pair(&mut tokens_a, &mut tokens_b, max_seq_len - added_tokens);
+        let (num_truncated_a, num_truncated_b) = truncate_seq_pair(
+            self.truncation_strategy,
+            &mut tokens_a,
+            &mut tokens_b,
+            max_seq_len - added_tokens,
+        )?;
 
-        //let mut segment_ids = vec![0];
-        SEGMENT_IDS.with(|segment_ids| {
-            let mut segment_ids = segment_ids.borrow_mut();
-            segment_ids.clear();
-            segment_ids.reserve(max_seq_len);
-            // + CLS && [SEP]
-            for _ in 0..tokens_a.len() + 2 {
-                segment_ids.push(0);
-            }
-            // +[SEP]
-            for _ in 0..tokens_b.len() + pair {
-                segment_ids.push(1);
+        let cls_token_id = self.cls_token_id as i64;
+        let sep_token_id = self.sep_token_id as i64;
+        let mut encoding = Encoding::with_capacity(max_seq_len);
+        // [CLS] A [SEP]
+        encoding.push_special(cls_token_id, self.token(self.cls_token_id), 0);
+        for piece in tokens_a {
+            encoding.push(&piece, self.token(piece.id as usize), 0);
+        }
+        encoding.push_special(sep_token_id, self.token(self.sep_token_id), 0);
+        // B [SEP]
+        if is_pair {
+            for piece in tokens_b {
+                encoding.push(&piece, self.token(piece.id as usize), 1);
             }
+            encoding.push_special(sep_token_id, self.token(self.sep_token_id), 1);
+        }
+        let target_len = self.padding.target_len(encoding.len(), max_seq_len);
+        self.pad_encoding(&mut encoding, target_len);
+        encoding.num_truncated_a = num_truncated_a;
+        encoding.num_truncated_b = num_truncated_b;
+        Ok(encoding)
+    }
 
-            while segment_ids.len() < max_seq_len {
-                segment_ids.push(0);
-            }
-        });
+    pub(crate) fn pad_encoding(&self, encoding: &mut Encoding, target_len: usize) {
+        let pad_id = self
+            .padding
+            .pad_id
+            .or_else(|| self.pad_token_id.map(|id| id as i64));
+        // the fallback id 0 may be an ordinary token, whose name is not used
+        let pad_token = pad_id
+            .and_then(|id| self.id_to_token(id as usize))
+            .unwrap_or("");
+        encoding.pad(target_len, &self.padding, pad_id.unwrap_or(0), pad_token);
+    }
 
-        INPUT_MASK.with(|input_mask| {
-            let mut input_mask = input_mask.borrow_mut();
-            input_mask.clear();
-            input_mask.reserve(max_seq_len);
-            // CLS + [SEP]*2
-            for _ in 0..tokens_a.len() + tokens_b.len() + added_tokens {
-                input_mask.push(1);
-            }
-            while input_mask.len() < max_seq_len {
-                input_mask.push(0);
+    /// Applies batch-level padding, which single encodes leave out.
+    fn pad_batch(&self, encodings: &mut [Encoding]) {
+        let longest = encodings.iter().map(Encoding::len).max().unwrap_or(0);
+        if let Some(target_len) = self.padding.batch_target_len(longest) {
+            for encoding in encodings {
+                self.pad_encoding(encoding, target_len);
             }
-        });
+        }
+    }
+
+    /// Encodes a single sequence as `[CLS] text [SEP]`.
+    ///
+    /// The sequence is truncated to `max_seq_len` with the configured
+    /// `TruncationStrategy` and padded as set by the `PaddingParams`, by
+    /// default to `max_seq_len`. A `max_seq_len` of 0 keeps the natural
+    /// length without padding, truncated to the `model_max_length` when the
+    /// tokenizer has one.
+    pub fn encode<T: AsRef<str>>(
+        &self,
+        text: T,
+        max_seq_len: usize,
+    ) -> Result<Encoding, Box<dyn Error + Send + Sync>> {
+        let tokens = self.tokenize_to_pieces(text.as_ref());
+        self.build_encoding(tokens, None, max_seq_len)
+    }
+
+    /// Encodes a sequence pair as `[CLS] text_a [SEP] text_b [SEP]`.
+    ///
+    /// See [`encode`](#method.encode).
+    pub fn encode_pair<T: AsRef<str>>(
+        &self,
+        text_a: T,
+        text_b: T,
+        max_seq_len: usize,
+    ) -> Result<Encoding, Box<dyn Error + Send + Sync>> {
+        let tokens_a = self.tokenize_to_pieces(text_a.as_ref());
+        let tokens_b = self.tokenize_to_pieces(text_b.as_ref());
+        self.build_encoding(tokens_a, Some(tokens_b), max_seq_len)
+    }
+
+    /// Encodes input already split into words, such as the words of an NER
+    /// dataset. Every word still goes through the basic tokenizer, so
+    /// punctuation inside a word is split off, but `word_ids` of the
+    /// encoding refer to the index in `words` and offsets are relative to
+    /// each word.
+    ///
+    /// See [`encode`](#method.encode).
+    pub fn encode_words<T: AsRef<str>>(
+        &self,
+        words: &[T],
+        max_seq_len: usize,
+    ) -> Result<Encoding, Box<dyn Error + Send + Sync>> {
+        let tokens = self.tokenize_words_to_pieces(words);
+        self.build_encoding(tokens, None, max_seq_len)
+    }
+
+    /// Pair version of [`encode_words`](#method.encode_words). Word ids of
+    /// the second sequence index into `words_b`.
+    pub fn encode_pair_words<T: AsRef<str>>(
+        &self,
+        words_a: &[T],
+        words_b: &[T],
+        max_seq_len: usize,
+    ) -> Result<Encoding, Box<dyn Error + Send + Sync>> {
+        let tokens_a = self.tokenize_words_to_pieces(words_a);
+        let tokens_b = self.tokenize_words_to_pieces(words_b);
+        self.build_encoding(tokens_a, Some(tokens_b), max_seq_len)
+    }
+
+    /// Encodes every text with [`encode`](#method.encode), spreading the
+    /// work over the rayon thread pool. The encodings are returned in input
+    /// order, or the first error is returned.
+    ///
+    /// With `PaddingStrategy::BatchLongest`, every encoding is padded to the
+    /// longest one in the batch.
+    pub fn encode_batch<T: AsRef<str> + Sync>(
+        &self,
+        texts: &[T],
+        max_seq_len: usize,
+    ) -> Result<Vec<Encoding>, Box<dyn Error + Send + Sync>> {
+        let mut encodings: Vec<Encoding> = texts
+            .par_iter()
+            .map(|text| self.encode(text, max_seq_len))
+            .collect::<Result<_, _>>()?;
+        self.pad_batch(&mut encodings);
+        Ok(encodings)
+    }
 
-        INPUT_IDS.with(|input_ids| {
-            let mut input_ids = input_ids.borrow_mut();
-            input_ids.clear();
-            input_ids.reserve(max_seq_len);
-            input_ids.push(self.cls_token_id as i64);
+    /// Pair version of [`encode_batch`](#method.encode_batch).
+    pub fn encode_pair_batch<T: AsRef<str> + Sync>(
+        &self,
+        pairs: &[(T, T)],
+        max_seq_len: usize,
+    ) -> Result<Vec<Encoding>, Box<dyn Error + Send + Sync>> {
+        let mut encodings: Vec<Encoding> = pairs
+            .par_iter()
+            .map(|(text_a, text_b)| self.encode_pair(text_a, text_b, max_seq_len))
+            .collect::<Result<_, _>>()?;
+        self.pad_batch(&mut encodings);
+        Ok(encodings)
+    }
 
-            input_ids.append(&mut tokens_a);
-            input_ids.push(self.sep_token_id as i64);
+    /// Encodes a question and a passage too long to fit next to it as
+    /// overlapping windows over the passage, as the doc-stride feature
+    /// generation of BERT on SQuAD.
+    ///
+    /// Every window is `[CLS] question [SEP] window [SEP]`, padded to
+    /// `max_seq_len`; consecutive windows start `doc_stride` tokens apart
+    /// and the last one reaches the end of the passage. `window_offset`
+    /// records where each window starts in the passage tokens.
+    pub fn encode_pair_with_overflow<T: AsRef<str>>(
+        &self,
+        question: T,
+        passage: T,
+        max_seq_len: usize,
+        doc_stride: usize,
+    ) -> Result<Vec<Encoding>, Box<dyn Error + Send + Sync>> {
+        let question = self.tokenize_to_pieces(question.as_ref());
+        let passage = self.tokenize_to_pieces(passage.as_ref());
+        if doc_stride == 0 {
+            return Err("doc_stride must be positive".into());
+        }
+        if question.len() + 3 >= max_seq_len {
+            return Err(format!(
+                "question of {} tokens leaves no room for the passage in {} tokens",
+                question.len(),
+                max_seq_len
+            )
+            .into());
+        }
 
-            if is_pair {
-                input_ids.append(&mut tokens_b);
-                input_ids.push(self.sep_token_id as i64);
+        let max_window_len = max_seq_len - question.len() - 3;
+        let mut encodings = Vec::new();
+        let mut start = 0;
+        loop {
+            let end = passage.len().min(start + max_window_len);
+            let window = passage[start..end].to_vec();
+            let mut encoding = self.build_encoding(question.clone(), Some(window), max_seq_len)?;
+            encoding.num_truncated_b = passage.len() - (end - start);
+            encoding.window_offset = start;
+            encodings.push(encoding);
+            if end == passage.len() {
+                break;
             }
+            start += doc_stride.min(end - start);
+        }
+        Ok(encodings)
+    }
 
-            while input_ids.len() < max_seq_len {
-                input_ids.push(0);
+    /// Batch version of
+    /// [`encode_pair_with_overflow`](#method.encode_pair_with_overflow), run
+    /// on the rayon thread pool. The windows of all pairs are returned in
+    /// input order; `overflow_to_sample` maps each back to its pair.
+    pub fn encode_pair_batch_with_overflow<T: AsRef<str> + Sync>(
+        &self,
+        pairs: &[(T, T)],
+        max_seq_len: usize,
+        doc_stride: usize,
+    ) -> Result<Vec<Encoding>, Box<dyn Error + Send + Sync>> {
+        let windows: Vec<Vec<Encoding>> = pairs
+            .par_iter()
+            .map(|(question, passage)| {
+                self.encode_pair_with_overflow(question, passage, max_seq_len, doc_stride)
+            })
+            .collect::<Result<_, _>>()?;
+        let mut encodings = Vec::new();
+        for (sample, windows) in windows.into_iter().enumerate() {
+            for mut encoding in windows {
+                encoding.overflow_to_sample = sample;
+                encodings.push(encoding);
             }
-        });
-        max_seq_len
+        }
+        self.pad_batch(&mut encodings);
+        Ok(encodings)
+    }
+
+    /// Encodes `text_a` (and `text_b` when `is_pair` is set) and stores the
+    /// result for the ffi getters of the calling thread.
+    ///
+    /// Returns the length of the stored sequences. Rust callers should use
+    /// [`encode`](#method.encode) or [`encode_pair`](#method.encode_pair)
+    /// instead, which return the `Encoding` directly.
+    pub fn convert_pairs<T: AsRef<str>>(
+        &self,
+        text_a: T,
+        text_b: T,
+        max_seq_len: usize,
+        is_pair: bool,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let encoding = if is_pair {
+            self.encode_pair(text_a, text_b, max_seq_len)?
+        } else {
+            self.encode(text_a, max_seq_len)?
+        };
+        let seq_len = encoding.len();
+        super::set_last_encoding(encoding);
+        Ok(seq_len)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use padding::{PaddingDirection, PaddingStrategy};
 
     #[test]
     fn test_is_punctuation() {
@@ -465,6 +973,7 @@ mod test {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_convert_tokens_to_ids() {
         let vocab_tokens = [
             "[UNK]", "[CLS]", "[SEP]", "want", "##want", "##ed", "wa", "un", "runn", "##ing",
@@ -480,7 +989,126 @@ mod test {
             .collect();
 
         let ids = convert_tokens_to_ids(&vocab, &tokens);
-        assert_eq!(ids, [7, 4, 5, 8, 9]);
+        assert_eq!(ids, [Some(7), Some(4), Some(5), Some(8), Some(9)]);
+        let tokens_and_unknown = ["want".to_string(), "unknown".to_string()];
+        assert_eq!(
+            convert_tokens_to_ids(&vocab, &tokens_and_unknown),
+            [Some(3), None]
+        );
+        let inv_vocab: IndexMap<usize, String> =
+            vocab.iter().map(|(k, &v)| (v, k.clone())).collect();
+        assert_eq!(
+            convert_ids_to_tokens(&inv_vocab, &[3, 42]),
+            [Some("want".to_string()), None]
+        );
+
+        let tokenizer = FullTokenizerBuilder::from_vocab(vocab)
+            .special_tokens(SpecialTokens {
+                pad_token: None,
+                mask_token: None,
+                ..SpecialTokens::default()
+            })
+            .build()
+            .unwrap();
+        let tokens = ["want".to_string(), "unknown".to_string()];
+        assert_eq!(tokenizer.convert_tokens_to_ids(&tokens), [3, 0]);
+        assert_eq!(tokenizer.convert_ids_to_tokens(&[3, 42]), ["want", "[UNK]"]);
+
+        assert_eq!(tokenizer.token_to_id("##ing"), Some(9));
+        assert_eq!(tokenizer.token_to_id("ing"), None);
+        assert_eq!(tokenizer.id_to_token(7), Some("un"));
+        assert_eq!(tokenizer.id_to_token(10), None);
+        assert_eq!(tokenizer.vocab_size(), 10);
+        assert!(tokenizer.contains("runn") && !tokenizer.contains("run"));
+        let vocab: Vec<_> = tokenizer.vocab().take(2).collect();
+        assert_eq!(vocab, [("[UNK]", 0), ("[CLS]", 1)]);
+    }
+
+    /// The original quadratic greedy longest-match-first, kept as the
+    /// reference for `WordpieceTrie`.
+    fn naive_tokenize_word(vocab: &Vocab, chars: &[char]) -> Option<Vec<(usize, usize, usize)>> {
+        let mut start = 0;
+        let mut sub_tokens = Vec::new();
+        while start < chars.len() {
+            let mut end = chars.len();
+            let mut cur_substr = None;
+            while start < end {
+                let mut substr: String = chars[start..end].iter().collect();
+                if start > 0 {
+                    substr = "##".to_string() + &substr;
+                }
+                if let Some(val) = vocab.get(&substr) {
+                    cur_substr = Some(*val);
+                    break;
+                }
+                end -= 1;
+            }
+            sub_tokens.push((cur_substr?, start, end));
+            start = end;
+        }
+        Some(sub_tokens)
+    }
+
+    /// xorshift64*, enough to generate reproducible random vocabularies.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 33) as usize % n
+        }
+
+        fn word(&mut self, alphabet: &[char], max_len: usize) -> String {
+            let len = 1 + self.below(max_len);
+            (0..len)
+                .map(|_| alphabet[self.below(alphabet.len())])
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_trie_matches_naive_wordpiece() {
+        let alphabet = ['a', 'b', 'c', '#'];
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        for _ in 0..200 {
+            let mut vocab = IndexMap::new();
+            for _ in 0..rng.below(30) {
+                let mut token = rng.word(&alphabet, 4);
+                if rng.below(2) == 0 {
+                    token = format!("##{}", token);
+                }
+                let id = vocab.len();
+                vocab.entry(token).or_insert(id);
+            }
+            let trie = WordpieceTrie::new(&vocab, "##");
+            for _ in 0..50 {
+                let chars: Vec<char> = rng.word(&alphabet, 12).chars().collect();
+                assert_eq!(
+                    trie.tokenize_word(&chars),
+                    naive_tokenize_word(&vocab, &chars),
+                    "vocab: {:?}, word: {:?}",
+                    vocab.keys().collect::<Vec<_>>(),
+                    chars
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_trie_matches_naive_wordpiece_on_vocab() {
+        let (vocab, _) = FullTokenizer::load_vocab("vocab.txt").unwrap();
+        let trie = WordpieceTrie::new(&vocab, "##");
+        let text = "unwanted running tokenization wordpiece ##abc a## \u{4F60}\u{597D} \
+                    supercalifragilisticexpialidocious 12345.678 hello-world";
+        for word in text.split_whitespace() {
+            let chars: Vec<char> = word.chars().collect();
+            assert_eq!(
+                trie.tokenize_word(&chars),
+                naive_tokenize_word(&vocab, &chars)
+            );
+        }
     }
 
     #[test]
@@ -494,7 +1122,7 @@ mod test {
             vocab.insert(token.to_string(), i);
             inv_vocab.insert(i, token.to_string());
         }
-        let tokenizer = WordpieceTokenizer::new(vocab, inv_vocab, "[UNK]", 100);
+        let tokenizer = WordpieceTokenizer::new(vocab, inv_vocab, "[UNK]", 100, "##");
 
         assert_eq!(tokenizer.tokenize("").len(), 0);
 
@@ -553,5 +1181,400 @@ mod test {
     }
 
     #[test]
-    fn test_full_tokenizer() {}
+    fn test_basic_tokenizer_offsets() {
+        let tokenizer = BasicTokenizer::new(true);
+        let text = "H\u{00E9}llo\u{0005} \u{4E16}\u{754C}!";
+        let tokens = tokenizer.tokenize_with_offsets(text);
+        let values: Vec<&str> = tokens.iter().map(|t| t.value.as_str()).collect();
+        assert_eq!(values, ["hello", "\u{4E16}", "\u{754C}", "!"]);
+        let offsets: Vec<(usize, usize)> = tokens.iter().map(|t| t.offsets).collect();
+        assert_eq!(offsets, [(0, 5), (7, 8), (8, 9), (9, 10)]);
+        let byte_offsets: Vec<(usize, usize)> = tokens.iter().map(|t| t.byte_offsets).collect();
+        assert_eq!(byte_offsets, [(0, 6), (8, 11), (11, 14), (14, 15)]);
+
+        // a combining accent is stripped but stays inside the span
+        let tokens = tokenizer.tokenize_with_offsets("He\u{0301}llo, \u{03A3}\u{039F}\u{03A3}");
+        let values: Vec<&str> = tokens.iter().map(|t| t.value.as_str()).collect();
+        assert_eq!(values, ["hello", ",", "\u{03C3}\u{03BF}\u{03C2}"]);
+        let offsets: Vec<(usize, usize)> = tokens.iter().map(|t| t.offsets).collect();
+        assert_eq!(offsets, [(0, 6), (6, 7), (8, 11)]);
+    }
+
+    #[test]
+    fn test_full_tokenizer_offsets() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let text = "\u{4F60}\u{597D} Hello running";
+        let tokens = tokenizer.tokenize_with_offsets(text);
+        assert_eq!(tokenizer.tokenize(text).len(), tokens.len());
+        for token in &tokens {
+            let (start, end) = token.byte_offsets;
+            let source = text[start..end].to_lowercase();
+            assert_eq!(source.replace(' ', ""), source);
+            assert!(source.contains(token.value.trim_start_matches("##")));
+        }
+
+        let encoding = tokenizer
+            .encode_pair("\u{4F60}\u{597D}", "hello", 0)
+            .unwrap();
+        assert_eq!(
+            encoding.offsets,
+            [(0, 0), (0, 1), (1, 2), (0, 0), (0, 5), (0, 0)]
+        );
+        assert_eq!(encoding.byte_offsets[2], (3, 6));
+    }
+
+    #[test]
+    fn test_encode_batch() {
+        let mut tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let texts: Vec<String> = (0..64).map(|i| "hello ".repeat(i % 7)).collect();
+
+        let encodings = tokenizer.encode_batch(&texts, 0).unwrap();
+        assert_eq!(encodings.len(), texts.len());
+        for (text, encoding) in texts.iter().zip(&encodings) {
+            assert_eq!(*encoding, tokenizer.encode(text, 0).unwrap());
+        }
+
+        tokenizer.set_padding(PaddingParams {
+            strategy: PaddingStrategy::BatchLongest,
+            ..PaddingParams::default()
+        });
+        let encodings = tokenizer.encode_batch(&texts, 0).unwrap();
+        assert!(encodings.iter().all(|e| e.len() == 8));
+        assert_eq!(encodings[1].input_mask, [1, 1, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(encodings[1].input_ids[3..], [0, 0, 0, 0, 0]);
+
+        let pairs = [("hello", "world"), ("\u{4F60}\u{597D}", "hello world")];
+        let encodings = tokenizer.encode_pair_batch(&pairs, 0).unwrap();
+        assert_eq!(encodings[0].len(), 7);
+        assert_eq!(
+            encodings[0].input_ids[..5],
+            tokenizer
+                .encode_pair("hello", "world", 0)
+                .unwrap()
+                .input_ids[..]
+        );
+        assert_eq!(
+            encodings[1],
+            tokenizer.encode_pair(pairs[1].0, pairs[1].1, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encode_words() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let words = ["Hello", "wanted,", "\u{4F60}\u{597D}"];
+        let encoding = tokenizer.encode_words(&words, 10).unwrap();
+        assert_eq!(
+            encoding.word_ids,
+            [
+                None,
+                Some(0),
+                Some(1),
+                Some(1),
+                Some(1),
+                Some(2),
+                Some(2),
+                None,
+                None,
+                None
+            ]
+        );
+        assert_eq!(encoding.offsets[3..5], [(4, 6), (6, 7)]);
+
+        let labels = [1, 2, 3];
+        let aligned = encoding.align_labels(&labels, false, -100).unwrap();
+        assert_eq!(aligned, [-100, 1, 2, -100, -100, 3, -100, -100, -100, -100]);
+        let aligned = encoding.align_labels(&labels, true, -100).unwrap();
+        assert_eq!(aligned, [-100, 1, 2, 2, 2, 3, 3, -100, -100, -100]);
+        assert!(encoding.align_labels(&labels[..2], false, -100).is_err());
+
+        // labels only cover the first sequence
+        let encoding = tokenizer
+            .encode_pair_words(&["hello"], &["world", "hello"], 0)
+            .unwrap();
+        assert_eq!(
+            encoding.word_ids,
+            [None, Some(0), None, Some(0), Some(1), None]
+        );
+        let aligned = encoding.align_labels(&[7], true, -1).unwrap();
+        assert_eq!(aligned, [-1, 7, -1, -1, -1, -1]);
+    }
+
+    #[test]
+    fn test_padding() {
+        let mut tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        tokenizer.set_padding(PaddingParams {
+            direction: PaddingDirection::Left,
+            pad_to_multiple_of: Some(4),
+            pad_type_id: 1,
+            ..PaddingParams::default()
+        });
+        let encoding = tokenizer.encode("hello world", 6).unwrap();
+        assert_eq!(encoding.input_ids, [0, 0, 0, 0, 101, 8701, 8572, 102]);
+        assert_eq!(encoding.input_mask, [0, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(encoding.segment_ids, [1, 1, 1, 1, 0, 0, 0, 0]);
+        assert_eq!(encoding.special_tokens_mask, [1, 1, 1, 1, 1, 0, 0, 1]);
+        assert_eq!(encoding.tokens[0], "[PAD]");
+        assert_eq!(encoding.offsets[4..7], [(0, 0), (0, 5), (6, 11)]);
+
+        tokenizer.set_padding(PaddingParams {
+            strategy: PaddingStrategy::Fixed(6),
+            pad_id: Some(-1),
+            pad_mask_value: -1,
+            ..PaddingParams::default()
+        });
+        let encoding = tokenizer.encode("hello", 0).unwrap();
+        assert_eq!(encoding.input_ids, [101, 8701, 102, -1, -1, -1]);
+        assert_eq!(encoding.input_mask, [1, 1, 1, -1, -1, -1]);
+
+        tokenizer.set_padding(PaddingParams {
+            strategy: PaddingStrategy::DoNotPad,
+            ..PaddingParams::default()
+        });
+        assert_eq!(tokenizer.encode("hello", 16).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_decode() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+
+        let encoding = tokenizer
+            .encode_pair(
+                "\u{4F60}\u{597D}\u{FF0C}\u{4E16}\u{754C}",
+                "Hello, running world!",
+                16,
+            )
+            .unwrap();
+        assert_eq!(
+            tokenizer.decode(&encoding.input_ids, true, true),
+            "\u{4F60}\u{597D}\u{FF0C}\u{4E16}\u{754C} hello, running world!"
+        );
+        assert_eq!(
+            tokenizer.decode(&encoding.input_ids[..4], false, false),
+            "[CLS] \u{4F60} \u{597D} \u{FF0C}"
+        );
+
+        let ids = tokenizer.tokenize_to_ids("tokenization");
+        assert!(ids.len() > 1);
+        assert_eq!(tokenizer.decode(&ids, true, true), "tokenization");
+
+        let batch = vec![ids.clone(), encoding.input_ids.clone()];
+        let decoded = tokenizer.decode_batch(&batch, true, false);
+        assert_eq!(decoded[0], "tokenization");
+        assert_eq!(
+            decoded[1],
+            tokenizer.decode(&encoding.input_ids, true, false)
+        );
+    }
+
+    #[test]
+    fn test_special_tokens() {
+        let mut vocab = IndexMap::new();
+        for token in &["<unk>", "<s>", "</s>", "hello", "<pad>", "<mask>"] {
+            let id = vocab.len();
+            vocab.insert(token.to_string(), id);
+        }
+        let special_tokens = SpecialTokens {
+            unk_token: "<unk>".to_string(),
+            cls_token: "<s>".to_string(),
+            sep_token: "</s>".to_string(),
+            pad_token: Some("<pad>".to_string()),
+            mask_token: Some("<mask>".to_string()),
+            additional_special_tokens: Vec::new(),
+        };
+        let tokenizer = FullTokenizerBuilder::from_vocab(vocab.clone())
+            .special_tokens(special_tokens.clone())
+            .build()
+            .unwrap();
+        assert_eq!(tokenizer.pad_token_id(), Some(4));
+        assert_eq!(tokenizer.mask_token_id(), Some(5));
+
+        let encoding = tokenizer.encode("hello world", 6).unwrap();
+        assert_eq!(encoding.input_ids, [1, 3, 0, 2, 4, 4]);
+        assert_eq!(
+            encoding.tokens,
+            ["<s>", "hello", "<unk>", "</s>", "<pad>", "<pad>"]
+        );
+        assert_eq!(
+            tokenizer.decode(&encoding.input_ids, true, true),
+            "hello <unk>"
+        );
+
+        let result = FullTokenizerBuilder::from_vocab(vocab)
+            .special_tokens(SpecialTokens {
+                additional_special_tokens: vec!["<e1>".to_string()],
+                ..special_tokens
+            })
+            .build();
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "special token `<e1>` (additional_special_tokens) not in vocab"
+        );
+
+        // the legacy constructor does not require `[PAD]` or `[MASK]`
+        let tokenizer = FullTokenizer::new("ffi/vocab", true).unwrap();
+        assert_eq!(tokenizer.pad_token_id(), None);
+        assert_eq!(tokenizer.mask_token_id(), None);
+        // id 0 pads but stays an ordinary token
+        let encoding = tokenizer.encode("un", 5).unwrap();
+        assert_eq!(encoding.input_ids, [1, 7, 2, 0, 0]);
+        assert_eq!(encoding.tokens[3], "");
+        assert_eq!(tokenizer.decode(&[0, 7], true, false), "[UNK] un");
+        assert!(FullTokenizer::builder("ffi/vocab").build().is_err());
+    }
+
+    #[test]
+    fn test_builder_options() {
+        let mut vocab = IndexMap::new();
+        for token in &[
+            "[PAD]",
+            "[UNK]",
+            "[MASK]",
+            "[CLS]",
+            "[SEP]",
+            "h\u{00E9}llo",
+            "hello",
+            "\u{4F60}\u{597D}",
+            "un",
+            "@@want",
+            "@@ed",
+        ] {
+            let id = vocab.len();
+            vocab.insert(token.to_string(), id);
+        }
+        let builder = || FullTokenizerBuilder::from_vocab(vocab.clone());
+
+        let tokenizer = builder().build().unwrap();
+        assert_eq!(
+            tokenizer.tokenize("H\u{00E9}llo \u{4F60}\u{597D}"),
+            ["hello", "[UNK]", "[UNK]"]
+        );
+
+        let tokenizer = builder()
+            .strip_accents(false)
+            .tokenize_chinese_chars(false)
+            .build()
+            .unwrap();
+        assert_eq!(
+            tokenizer.tokenize("H\u{00E9}llo \u{4F60}\u{597D}"),
+            ["h\u{00E9}llo", "\u{4F60}\u{597D}"]
+        );
+
+        let tokenizer = builder()
+            .do_lower_case(false)
+            .strip_accents(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            tokenizer.tokenize("H\u{00E9}llo h\u{00E9}llo"),
+            ["[UNK]", "hello"]
+        );
+
+        let tokenizer = builder()
+            .continuing_subword_prefix("@@")
+            .max_input_chars_per_word(6)
+            .build()
+            .unwrap();
+        assert_eq!(
+            tokenizer.tokenize("unwant unwanted"),
+            ["un", "@@want", "[UNK]"]
+        );
+        let ids = tokenizer.tokenize_to_ids("unwant");
+        assert_eq!(tokenizer.decode(&ids, true, true), "unwant");
+
+        assert!(builder().continuing_subword_prefix("").build().is_err());
+    }
+
+    #[test]
+    fn test_encode_truncation_strategy() {
+        let mut tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let text_a = "\u{4F60}\u{597D}\u{4E16}\u{754C}";
+
+        let encoding = tokenizer.encode_pair(text_a, "hello world", 6).unwrap();
+        assert_eq!(
+            encoding.tokens,
+            ["[CLS]", "\u{4F60}", "\u{597D}", "[SEP]", "hello", "[SEP]"]
+        );
+        assert_eq!((encoding.num_truncated_a, encoding.num_truncated_b), (2, 1));
+
+        tokenizer.set_truncation_strategy(TruncationStrategy::OnlySecond);
+        assert!(tokenizer.encode_pair(text_a, "hello world", 6).is_err());
+        let encoding = tokenizer.encode_pair(text_a, "hello world", 8).unwrap();
+        assert_eq!((encoding.num_truncated_a, encoding.num_truncated_b), (0, 1));
+
+        tokenizer.set_truncation_strategy(TruncationStrategy::HeadTail { head: 1, tail: 1 });
+        let encoding = tokenizer.encode(text_a, 4).unwrap();
+        assert_eq!(encoding.tokens, ["[CLS]", "\u{4F60}", "\u{754C}", "[SEP]"]);
+        assert_eq!(encoding.num_truncated_a, 2);
+
+        tokenizer.set_truncation_strategy(TruncationStrategy::DoNotTruncate);
+        assert!(tokenizer.encode(text_a, 6).is_ok());
+        assert!(tokenizer.encode(text_a, 5).is_err());
+        assert!(tokenizer.encode(text_a, 1).is_err());
+    }
+
+    #[test]
+    fn test_encode_pair_with_overflow() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        let passage = "\u{4E00}\u{4E8C}\u{4E09}\u{56DB}\u{4E94}\u{516D}\u{4E03}";
+        let passage_ids = tokenizer.tokenize_to_ids(passage);
+
+        let windows = tokenizer
+            .encode_pair_with_overflow("hello", passage, 8, 2)
+            .unwrap();
+        let offsets: Vec<usize> = windows.iter().map(|e| e.window_offset).collect();
+        assert_eq!(offsets, [0, 2, 4]);
+        for window in &windows {
+            assert_eq!(window.len(), 8);
+            assert_eq!(window.input_ids[..3], [101, 8701, 102]);
+            let start = window.window_offset;
+            let len = window.segment_ids.iter().sum::<i64>() as usize - 1;
+            assert_eq!(
+                window.input_ids[3..3 + len],
+                passage_ids[start..start + len]
+            );
+            assert_eq!(window.num_truncated_b, passage_ids.len() - len);
+            // offsets still point into the full passage
+            assert_eq!(window.offsets[3], (start, start + 1));
+        }
+        assert_eq!(windows[2].input_mask, [1, 1, 1, 1, 1, 1, 1, 0]);
+
+        let windows = tokenizer
+            .encode_pair_with_overflow("hello", "\u{4E00}", 8, 2)
+            .unwrap();
+        assert_eq!(windows.len(), 1);
+        assert!(tokenizer
+            .encode_pair_with_overflow("hello world", passage, 5, 2)
+            .is_err());
+
+        let pairs = [("hello", passage), ("world", "\u{4E00}")];
+        let windows = tokenizer
+            .encode_pair_batch_with_overflow(&pairs, 8, 2)
+            .unwrap();
+        let samples: Vec<usize> = windows.iter().map(|e| e.overflow_to_sample).collect();
+        assert_eq!(samples, [0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_full_tokenizer() {
+        let tokenizer = FullTokenizer::new("vocab.txt", true).unwrap();
+        assert_eq!(tokenizer.tokenize("你好 Hello"), ["你", "好", "hello"]);
+
+        let encoding = tokenizer.encode_pair("你好", "hello world", 8).unwrap();
+        assert_eq!(
+            encoding.input_ids,
+            [101, 872, 1962, 102, 8701, 8572, 102, 0]
+        );
+        assert_eq!(encoding.input_mask, [1, 1, 1, 1, 1, 1, 1, 0]);
+        assert_eq!(encoding.segment_ids, [0, 0, 0, 0, 1, 1, 1, 0]);
+        assert_eq!(encoding.special_tokens_mask, [1, 0, 0, 1, 0, 0, 1, 1]);
+        assert_eq!(
+            encoding.tokens,
+            ["[CLS]", "你", "好", "[SEP]", "hello", "world", "[SEP]", "[PAD]"]
+        );
+
+        let encoding = tokenizer.encode("你好 hello world", 4).unwrap();
+        assert_eq!(encoding.input_ids, [101, 872, 1962, 102]);
+        assert_eq!(encoding.segment_ids, [0, 0, 0, 0]);
+    }
 }
@@ -0,0 +1,780 @@
+use serde_json::{self, json, Map, Value};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+
+use builder::{FullTokenizerBuilder, SpecialTokens};
+use padding::{PaddingDirection, PaddingParams, PaddingStrategy};
+use tokenization::{FullTokenizer, InvVocab, Vocab};
+use truncation::TruncationStrategy;
+
+fn unsupported(component: &str, value: &Value) -> Box<dyn Error> {
+    format!("unsupported {} in tokenizer.json: {}", component, value).into()
+}
+
+fn missing(component: &str, key: &str) -> Box<dyn Error> {
+    format!("tokenizer.json: {} has no valid `{}`", component, key).into()
+}
+
+fn component_type(value: &Value) -> Option<&str> {
+    value.get("type").and_then(Value::as_str)
+}
+
+fn get_str<'a>(value: &'a Value, component: &str, key: &str) -> Result<&'a str, Box<dyn Error>> {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| missing(component, key))
+}
+
+fn get_usize(value: &Value, component: &str, key: &str) -> Result<usize, Box<dyn Error>> {
+    value
+        .get(key)
+        .and_then(Value::as_u64)
+        .map(|n| n as usize)
+        .ok_or_else(|| missing(component, key))
+}
+
+/// A boolean that may be absent or null, which yields `default`.
+fn get_bool_or(
+    value: &Value,
+    component: &str,
+    key: &str,
+    default: bool,
+) -> Result<bool, Box<dyn Error>> {
+    match value.get(key) {
+        None | Some(&Value::Null) => Ok(default),
+        Some(&Value::Bool(b)) => Ok(b),
+        Some(_) => Err(missing(component, key)),
+    }
+}
+
+struct WordPieceModel {
+    vocab: Vocab,
+    inv_vocab: InvVocab,
+    unk_token: String,
+    continuing_subword_prefix: String,
+    max_input_chars_per_word: usize,
+}
+
+fn parse_model(model: &Value) -> Result<WordPieceModel, Box<dyn Error>> {
+    if component_type(model) != Some("WordPiece") {
+        return Err(unsupported("model", model.get("type").unwrap_or(model)));
+    }
+    let entries = model
+        .get("vocab")
+        .and_then(Value::as_object)
+        .ok_or_else(|| missing("model", "vocab"))?;
+    let mut tokens = Vec::with_capacity(entries.len());
+    for (token, id) in entries {
+        let id = id.as_u64().ok_or_else(|| missing("model", "vocab"))?;
+        tokens.push((id as usize, token.clone()));
+    }
+    // the keys of a JSON object are unordered, the ids give the file order
+    tokens.sort();
+    let vocab = tokens
+        .iter()
+        .map(|&(id, ref token)| (token.clone(), id))
+        .collect();
+    let inv_vocab = tokens.into_iter().collect();
+
+    Ok(WordPieceModel {
+        vocab,
+        inv_vocab,
+        unk_token: get_str(model, "model", "unk_token")?.to_string(),
+        continuing_subword_prefix: match model.get("continuing_subword_prefix") {
+            None | Some(&Value::Null) => "##".to_string(),
+            Some(_) => get_str(model, "model", "continuing_subword_prefix")?.to_string(),
+        },
+        max_input_chars_per_word: match model.get("max_input_chars_per_word") {
+            None | Some(&Value::Null) => 100,
+            Some(_) => get_usize(model, "model", "max_input_chars_per_word")?,
+        },
+    })
+}
+
+fn apply_normalizer(
+    builder: FullTokenizerBuilder,
+    normalizer: &Value,
+) -> Result<FullTokenizerBuilder, Box<dyn Error>> {
+    if component_type(normalizer) != Some("BertNormalizer") {
+        return Err(unsupported("normalizer", normalizer));
+    }
+    if !get_bool_or(normalizer, "normalizer", "clean_text", true)? {
+        return Err(unsupported("normalizer", normalizer));
+    }
+    let lowercase = get_bool_or(normalizer, "normalizer", "lowercase", true)?;
+    let strip_accents = get_bool_or(normalizer, "normalizer", "strip_accents", lowercase)?;
+    let handle_chinese_chars = get_bool_or(normalizer, "normalizer", "handle_chinese_chars", true)?;
+    Ok(builder
+        .do_lower_case(lowercase)
+        .strip_accents(strip_accents)
+        .tokenize_chinese_chars(handle_chinese_chars))
+}
+
+/// One item of a `TemplateProcessing` template: a special token or a
+/// sequence, with its type id.
+fn template_item(item: &Value) -> Option<(bool, &str, u64)> {
+    let (is_special, piece) = match (item.get("SpecialToken"), item.get("Sequence")) {
+        (Some(piece), None) => (true, piece),
+        (None, Some(piece)) => (false, piece),
+        _ => return None,
+    };
+    let id = piece.get("id")?.as_str()?;
+    let type_id = piece.get("type_id")?.as_u64()?;
+    Some((is_special, id, type_id))
+}
+
+/// Returns the `[CLS]` and `[SEP]` tokens of a post-processor that builds
+/// `[CLS] A [SEP]` and `[CLS] A [SEP] B [SEP]` with type ids 0 and 1, the
+/// only layout `FullTokenizer` produces.
+fn parse_post_processor(post_processor: &Value) -> Result<(String, String), Box<dyn Error>> {
+    match component_type(post_processor) {
+        Some("BertProcessing") => {
+            let token = |key| {
+                post_processor
+                    .get(key)
+                    .and_then(|pair: &Value| pair.get(0))
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .ok_or_else(|| missing("post_processor", key))
+            };
+            Ok((token("cls")?, token("sep")?))
+        }
+        Some("TemplateProcessing") => {
+            let template = |key| -> Option<Vec<(bool, &str, u64)>> {
+                post_processor
+                    .get(key)?
+                    .as_array()?
+                    .iter()
+                    .map(template_item)
+                    .collect()
+            };
+            let (single, pair) = match (template("single"), template("pair")) {
+                (Some(single), Some(pair)) => (single, pair),
+                _ => return Err(unsupported("post_processor", post_processor)),
+            };
+            if single.len() != 3 || !single[0].0 || !single[2].0 {
+                return Err(unsupported("post_processor", post_processor));
+            }
+            let (cls, sep) = (single[0].1, single[2].1);
+            let expected_single = [(true, cls, 0), (false, "A", 0), (true, sep, 0)];
+            let expected_pair = [
+                (true, cls, 0),
+                (false, "A", 0),
+                (true, sep, 0),
+                (false, "B", 1),
+                (true, sep, 1),
+            ];
+            if single[..] != expected_single[..] || pair[..] != expected_pair[..] {
+                return Err(unsupported("post_processor", post_processor));
+            }
+            Ok((cls.to_string(), sep.to_string()))
+        }
+        _ => Err(unsupported("post_processor", post_processor)),
+    }
+}
+
+fn parse_truncation(truncation: &Value) -> Result<TruncationStrategy, Box<dyn Error>> {
+    if truncation.is_null() {
+        return Ok(TruncationStrategy::default());
+    }
+    match truncation.get("direction").and_then(Value::as_str) {
+        None | Some("Right") => {}
+        Some(_) => return Err(unsupported("truncation", truncation)),
+    }
+    match truncation.get("strategy").and_then(Value::as_str) {
+        Some("LongestFirst") => Ok(TruncationStrategy::LongestFirst),
+        Some("OnlyFirst") => Ok(TruncationStrategy::OnlyFirst),
+        Some("OnlySecond") => Ok(TruncationStrategy::OnlySecond),
+        _ => Err(unsupported("truncation", truncation)),
+    }
+}
+
+/// Returns the padding parameters and the pad token. Without padding the
+/// crate default of padding to `max_seq_len` is kept.
+fn parse_padding(padding: &Value) -> Result<(PaddingParams, Option<String>), Box<dyn Error>> {
+    if padding.is_null() {
+        return Ok((PaddingParams::default(), None));
+    }
+    let strategy = match padding.get("strategy") {
+        Some(Value::String(s)) if s == "BatchLongest" => PaddingStrategy::BatchLongest,
+        Some(strategy) => match strategy.get("Fixed").and_then(Value::as_u64) {
+            Some(len) => PaddingStrategy::Fixed(len as usize),
+            None => return Err(unsupported("padding", padding)),
+        },
+        None => return Err(missing("padding", "strategy")),
+    };
+    let direction = match padding.get("direction").and_then(Value::as_str) {
+        None | Some("Right") => PaddingDirection::Right,
+        Some("Left") => PaddingDirection::Left,
+        Some(_) => return Err(unsupported("padding", padding)),
+    };
+    let params = PaddingParams {
+        strategy,
+        direction,
+        pad_to_multiple_of: padding
+            .get("pad_to_multiple_of")
+            .and_then(Value::as_u64)
+            .map(|n| n as usize),
+        pad_id: padding.get("pad_id").and_then(Value::as_i64),
+        pad_type_id: padding
+            .get("pad_type_id")
+            .and_then(Value::as_i64)
+            .unwrap_or(0),
+        pad_mask_value: 0,
+    };
+    let pad_token = padding
+        .get("pad_token")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    Ok((params, pad_token))
+}
+
+/// Configures a builder from a parsed Hugging Face `tokenizer.json`.
+pub(crate) fn builder_from_json(json: &Value) -> Result<FullTokenizerBuilder, Box<dyn Error>> {
+    let model = parse_model(&json["model"])?;
+    let vocab = model.vocab;
+    let inv_vocab = model.inv_vocab;
+    let unk_token = model.unk_token;
+
+    let mut added_tokens = Vec::new();
+    let mut added_vocab = Vocab::new();
+    let mut added_special_tokens = Vec::new();
+    // roles of special tokens, only written by `save_tokenizer_json`
+    let mut roles = Vec::new();
+    if let Some(tokens) = json.get("added_tokens").and_then(Value::as_array) {
+        for token in tokens {
+            let content = get_str(token, "added_tokens", "content")?.to_string();
+            let id = get_usize(token, "added_tokens", "id")?;
+            let special = get_bool_or(token, "added_tokens", "special", false)?;
+            let matched = get_bool_or(token, "added_tokens", "matched", true)?;
+            if let Some(token_roles) = token.get("roles") {
+                let token_roles = token_roles
+                    .as_array()
+                    .ok_or_else(|| missing("added_tokens", "roles"))?;
+                for role in token_roles {
+                    let role = role
+                        .as_str()
+                        .ok_or_else(|| missing("added_tokens", "roles"))?;
+                    roles.push((role.to_string(), content.clone()));
+                }
+            }
+            // only verbatim matching on the raw text is implemented
+            for &(flag, default) in &[
+                ("single_word", false),
+                ("lstrip", false),
+                ("rstrip", false),
+                ("normalized", !special),
+            ] {
+                if get_bool_or(token, "added_tokens", flag, default)? {
+                    return Err(format!(
+                        "unsupported added token in tokenizer.json: `{}` sets `{}`",
+                        content, flag
+                    )
+                    .into());
+                }
+            }
+            match vocab.get(&content) {
+                Some(&vocab_id) if vocab_id != id => {
+                    return Err(format!(
+                        "tokenizer.json: added token `{}` has id {} but {} in the vocab",
+                        content, id, vocab_id
+                    )
+                    .into())
+                }
+                Some(_) => {}
+                None if inv_vocab.contains_key(&id) || added_vocab.values().any(|&i| i == id) => {
+                    return Err(format!(
+                        "tokenizer.json: added token `{}` reuses the id {}",
+                        content, id
+                    )
+                    .into())
+                }
+                None => {
+                    added_vocab.insert(content.clone(), id);
+                }
+            }
+            if special {
+                added_special_tokens.push(content.clone());
+            }
+            if matched {
+                added_tokens.push(content);
+            }
+        }
+    }
+
+    let pre_tokenizer = &json["pre_tokenizer"];
+    if component_type(pre_tokenizer) != Some("BertPreTokenizer") {
+        return Err(unsupported("pre_tokenizer", pre_tokenizer));
+    }
+    match component_type(&json["decoder"]) {
+        None if json["decoder"].is_null() => {}
+        Some("WordPiece") => {}
+        _ => return Err(unsupported("decoder", &json["decoder"])),
+    }
+    let (cls_token, sep_token) = parse_post_processor(&json["post_processor"])?;
+    let truncation_strategy = parse_truncation(&json["truncation"])?;
+    let (padding, pad_token) = parse_padding(&json["padding"])?;
+
+    let (pad_token, mask_token, additional_special_tokens) = if roles.is_empty() {
+        // files from `tokenizers` name no roles: take the usual BERT names
+        let contains =
+            |token: &String| vocab.contains_key(token) || added_vocab.contains_key(token);
+        let pad_token = pad_token.or_else(|| Some("[PAD]".to_string()).filter(contains));
+        let mask_token = Some("[MASK]".to_string()).filter(contains);
+        let additional_special_tokens = added_special_tokens
+            .into_iter()
+            .filter(|token| {
+                !(token == &unk_token || token == &cls_token || token == &sep_token)
+                    && pad_token.as_ref() != Some(token)
+                    && mask_token.as_ref() != Some(token)
+            })
+            .collect();
+        (pad_token, mask_token, additional_special_tokens)
+    } else {
+        let with_role = |name: &str| {
+            roles
+                .iter()
+                .filter(|&(role, _)| role == name)
+                .map(|(_, token)| token.clone())
+                .collect::<Vec<_>>()
+        };
+        (
+            with_role("pad_token").pop(),
+            with_role("mask_token").pop(),
+            with_role("additional_special_tokens"),
+        )
+    };
+    let special_tokens = SpecialTokens {
+        unk_token,
+        cls_token,
+        sep_token,
+        pad_token,
+        mask_token,
+        additional_special_tokens,
+    };
+
+    let builder = FullTokenizerBuilder::from_vocabs(vocab, inv_vocab)
+        .continuing_subword_prefix(model.continuing_subword_prefix)
+        .max_input_chars_per_word(model.max_input_chars_per_word)
+        .special_tokens(special_tokens)
+        .truncation_strategy(truncation_strategy)
+        .padding(padding)
+        .added_tokens(added_tokens)
+        .added_vocab(added_vocab);
+    apply_normalizer(builder, &json["normalizer"])
+}
+
+impl FullTokenizer {
+    /// Loads a Hugging Face `tokenizer.json` made of a `BertNormalizer`, a
+    /// `BertPreTokenizer`, a `WordPiece` model and a `BertProcessing` or
+    /// equivalent `TemplateProcessing` post-processor. Any other component
+    /// is rejected.
+    ///
+    /// Added tokens are matched verbatim in the raw input, so ones that set
+    /// `single_word`, `lstrip`, `rstrip` or `normalized` are rejected. Those
+    /// outside the WordPiece vocabulary keep their own ids and are never
+    /// produced by WordPiece. The truncation strategy and padding are taken
+    /// over; `max_length` is not, as `max_seq_len` is given to every
+    /// `encode` call. Without a `padding` block encodings are padded to
+    /// `max_seq_len`, the crate default.
+    ///
+    /// Pad, mask and additional special tokens are found by the `roles` of
+    /// the added tokens that `save_tokenizer_json` writes; files without
+    /// them use the special added tokens, taking `[PAD]` and `[MASK]` by
+    /// name.
+    pub fn from_tokenizer_json<T: AsRef<str>>(path: T) -> Result<FullTokenizer, Box<dyn Error>> {
+        let file = File::open(path.as_ref())?;
+        let json: Value = serde_json::from_reader(BufReader::new(file))?;
+        builder_from_json(&json)?.build()
+    }
+
+    /// Writes a Hugging Face `tokenizer.json` that tokenizes like this
+    /// tokenizer, readable by `tokenizers` and
+    /// [`from_tokenizer_json`](#method.from_tokenizer_json).
+    ///
+    /// Padding is kept when it is `BatchLongest` or `Fixed`, which
+    /// `tokenizers` supports too. Padding to `max_seq_len`, the default, is
+    /// written as no padding, which reads back as the default, so
+    /// `DoNotPad` is not kept. The truncation strategy is left out, as
+    /// `tokenizers` stores it together with a fixed `max_length`.
+    ///
+    /// Every special token is written as a special added token with its
+    /// `roles`; those not matched in the input here are marked
+    /// `"matched": false`. `tokenizers` ignores both keys and matches all
+    /// added tokens.
+    pub fn save_tokenizer_json<T: AsRef<str>>(&self, path: T) -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(path.as_ref())?);
+        serde_json::to_writer_pretty(&mut writer, &tokenizer_to_json(self))?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Builds the `tokenizer.json` equivalent of `tokenizer`.
+pub(crate) fn tokenizer_to_json(tokenizer: &FullTokenizer) -> Value {
+    let basic = &tokenizer.basic_tokenizer;
+    let wordpiece = &tokenizer.wordpiece_tokenizer;
+    let special_tokens = &tokenizer.special_tokens;
+    let is_matched = |token: &str| {
+        tokenizer
+            .added_tokens
+            .iter()
+            .any(|(added, _)| added == token)
+    };
+    let entry = |content: &str, special: bool| {
+        // matched on the raw text, before normalization
+        json!({
+            "id": tokenizer.token_to_id(content).unwrap(),
+            "content": content,
+            "single_word": false,
+            "lstrip": false,
+            "rstrip": false,
+            "normalized": false,
+            "special": special,
+        })
+    };
+
+    let mut added_tokens: Vec<Value> = Vec::new();
+    for (role, token) in special_tokens.with_roles() {
+        if let Some(entry) = added_tokens
+            .iter_mut()
+            .find(|entry| entry["content"] == token)
+        {
+            entry["roles"]
+                .as_array_mut()
+                .unwrap()
+                .push(Value::from(role));
+            continue;
+        }
+        let mut entry = entry(token, true);
+        entry["roles"] = json!([role]);
+        if !is_matched(token) {
+            entry["matched"] = Value::from(false);
+        }
+        added_tokens.push(entry);
+    }
+    for (content, _) in &tokenizer.added_tokens {
+        if !added_tokens
+            .iter()
+            .any(|entry| entry["content"] == **content)
+        {
+            added_tokens.push(entry(content, false));
+        }
+    }
+
+    let padding = match tokenizer.padding.strategy {
+        PaddingStrategy::BatchLongest => Some(Value::from("BatchLongest")),
+        PaddingStrategy::Fixed(len) => Some(json!({ "Fixed": len })),
+        // `tokenizers` has no padding to a length given per call
+        PaddingStrategy::DoNotPad | PaddingStrategy::MaxSeqLen => None,
+    };
+    let padding = padding.map_or(Value::Null, |strategy| {
+        let padding = &tokenizer.padding;
+        let pad_id = padding
+            .pad_id
+            .or_else(|| tokenizer.pad_token_id.map(|id| id as i64));
+        let pad_token = pad_id
+            .and_then(|id| tokenizer.id_to_token(id as usize))
+            .unwrap_or("");
+        let pad_id = pad_id.unwrap_or(0);
+        json!({
+            "strategy": strategy,
+            "direction": match padding.direction {
+                PaddingDirection::Right => "Right",
+                PaddingDirection::Left => "Left",
+            },
+            "pad_to_multiple_of": padding.pad_to_multiple_of,
+            "pad_id": pad_id,
+            "pad_type_id": padding.pad_type_id,
+            "pad_token": pad_token,
+        })
+    });
+
+    let cls = &special_tokens.cls_token;
+    let sep = &special_tokens.sep_token;
+    let mut template_tokens = Map::new();
+    for &(token, id) in &[(cls, tokenizer.cls_token_id), (sep, tokenizer.sep_token_id)] {
+        template_tokens.insert(
+            token.clone(),
+            json!({ "id": token, "ids": [id], "tokens": [token] }),
+        );
+    }
+    // added tokens outside the WordPiece vocabulary only appear in
+    // `added_tokens`
+    let vocab: Map<String, Value> = wordpiece
+        .vocab
+        .iter()
+        .map(|(token, &id)| (token.clone(), Value::from(id)))
+        .collect();
+
+    json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": padding,
+        "added_tokens": added_tokens,
+        "normalizer": {
+            "type": "BertNormalizer",
+            "clean_text": true,
+            "handle_chinese_chars": basic.tokenize_chinese_chars,
+            "strip_accents": basic.strip_accents,
+            "lowercase": basic.do_lower_case,
+        },
+        "pre_tokenizer": {"type": "BertPreTokenizer"},
+        "post_processor": {
+            "type": "TemplateProcessing",
+            "single": [
+                {"SpecialToken": {"id": cls, "type_id": 0}},
+                {"Sequence": {"id": "A", "type_id": 0}},
+                {"SpecialToken": {"id": sep, "type_id": 0}},
+            ],
+            "pair": [
+                {"SpecialToken": {"id": cls, "type_id": 0}},
+                {"Sequence": {"id": "A", "type_id": 0}},
+                {"SpecialToken": {"id": sep, "type_id": 0}},
+                {"Sequence": {"id": "B", "type_id": 1}},
+                {"SpecialToken": {"id": sep, "type_id": 1}},
+            ],
+            "special_tokens": template_tokens,
+        },
+        "decoder": {
+            "type": "WordPiece",
+            "prefix": wordpiece.continuing_subword_prefix,
+            "cleanup": true,
+        },
+        "model": {
+            "type": "WordPiece",
+            "unk_token": special_tokens.unk_token,
+            "continuing_subword_prefix": wordpiece.continuing_subword_prefix,
+            "max_input_chars_per_word": wordpiece.max_input_chars_per_word,
+            "vocab": vocab,
+        },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tokenizer_json() -> Value {
+        let vocab = [
+            "[PAD]", "[UNK]", "[CLS]", "[SEP]", "[MASK]", "want", "##want", "##ed", "wa", "un",
+            "runn", "##ing", ",",
+        ];
+        let vocab: serde_json::Map<String, Value> = vocab
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), Value::from(id)))
+            .collect();
+        serde_json::json!({
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [
+                {"id": 0, "content": "[PAD]", "special": true},
+                {"id": 4, "content": "[MASK]", "special": true},
+                {"id": 13, "content": "<e>", "special": true},
+                {"id": 14, "content": "wanted", "special": false, "normalized": false}
+            ],
+            "normalizer": {
+                "type": "BertNormalizer",
+                "clean_text": true,
+                "handle_chinese_chars": true,
+                "strip_accents": null,
+                "lowercase": true
+            },
+            "pre_tokenizer": {"type": "BertPreTokenizer"},
+            "post_processor": {
+                "type": "TemplateProcessing",
+                "single": [
+                    {"SpecialToken": {"id": "[CLS]", "type_id": 0}},
+                    {"Sequence": {"id": "A", "type_id": 0}},
+                    {"SpecialToken": {"id": "[SEP]", "type_id": 0}}
+                ],
+                "pair": [
+                    {"SpecialToken": {"id": "[CLS]", "type_id": 0}},
+                    {"Sequence": {"id": "A", "type_id": 0}},
+                    {"SpecialToken": {"id": "[SEP]", "type_id": 0}},
+                    {"Sequence": {"id": "B", "type_id": 1}},
+                    {"SpecialToken": {"id": "[SEP]", "type_id": 1}}
+                ],
+                "special_tokens": {}
+            },
+            "decoder": {"type": "WordPiece", "prefix": "##", "cleanup": true},
+            "model": {
+                "type": "WordPiece",
+                "unk_token": "[UNK]",
+                "continuing_subword_prefix": "##",
+                "max_input_chars_per_word": 100,
+                "vocab": vocab
+            }
+        })
+    }
+
+    #[test]
+    fn test_from_tokenizer_json() {
+        let tokenizer = builder_from_json(&tokenizer_json())
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(tokenizer.cls_token_id(), 2);
+        assert_eq!(tokenizer.pad_token_id(), Some(0));
+        assert_eq!(tokenizer.mask_token_id(), Some(4));
+        assert_eq!(
+            tokenizer.special_tokens().additional_special_tokens,
+            ["<e>"]
+        );
+        assert_eq!(
+            tokenizer.tokenize("UNwant\u{00E9}d,[MASK]<e>running Wanted"),
+            ["un", "##want", "##ed", ",", "[MASK]", "<e>", "runn", "##ing", "want", "##ed"]
+        );
+        // added tokens outside the vocab are only matched verbatim, so the
+        // lowercased "Wanted" above goes through WordPiece
+        assert_eq!(
+            tokenizer.tokenize("wanted unwanted"),
+            ["wanted", "un", "wanted"]
+        );
+        assert_eq!(tokenizer.token_to_id("wanted"), Some(14));
+        assert_eq!(tokenizer.vocab_size(), 15);
+
+        let encoding = tokenizer.encode("un<e>", 0).unwrap();
+        assert_eq!(encoding.input_ids, [2, 9, 13, 3]);
+        assert_eq!(encoding.offsets, [(0, 0), (0, 2), (2, 5), (0, 0)]);
+        assert_eq!(tokenizer.decode(&encoding.input_ids, true, true), "un");
+        // without a padding block encodings are padded to max_seq_len
+        let encoding = tokenizer.encode("un<e>", 8).unwrap();
+        assert_eq!(encoding.input_ids, [2, 9, 13, 3, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_unsupported_components() {
+        let error = |json: Value| builder_from_json(&json).err().unwrap().to_string();
+
+        let mut json = tokenizer_json();
+        json["normalizer"] = serde_json::json!({"type": "Lowercase"});
+        assert!(error(json).starts_with("unsupported normalizer"));
+
+        for &(flag, value) in &[
+            ("lstrip", true),
+            ("single_word", true),
+            ("normalized", true),
+        ] {
+            let mut json = tokenizer_json();
+            json["added_tokens"][2][flag] = Value::from(value);
+            assert_eq!(
+                error(json),
+                format!(
+                    "unsupported added token in tokenizer.json: `<e>` sets `{}`",
+                    flag
+                )
+            );
+        }
+        let mut json = tokenizer_json();
+        json["added_tokens"][3]["normalized"] = Value::Null;
+        assert!(error(json).ends_with("`wanted` sets `normalized`"));
+
+        let mut json = tokenizer_json();
+        json["pre_tokenizer"] = serde_json::json!({"type": "Whitespace"});
+        assert!(error(json).starts_with("unsupported pre_tokenizer"));
+
+        let mut json = tokenizer_json();
+        json["model"]["type"] = Value::from("BPE");
+        assert!(error(json).starts_with("unsupported model"));
+
+        let mut json = tokenizer_json();
+        json["post_processor"]["pair"][3]["Sequence"]["type_id"] = Value::from(0);
+        assert!(error(json).starts_with("unsupported post_processor"));
+
+        let mut json = tokenizer_json();
+        json["post_processor"] = serde_json::json!({
+            "type": "BertProcessing",
+            "sep": ["[SEP]", 3],
+            "cls": ["[CLS]", 2]
+        });
+        json["padding"] = serde_json::json!({
+            "strategy": {"Fixed": 8},
+            "direction": "Left",
+            "pad_id": 0,
+            "pad_type_id": 0,
+            "pad_token": "[PAD]"
+        });
+        let tokenizer = builder_from_json(&json).unwrap().build().unwrap();
+        assert_eq!(
+            tokenizer.encode("un", 0).unwrap().input_ids,
+            [0, 0, 0, 0, 0, 2, 9, 3]
+        );
+    }
+
+    #[test]
+    fn test_save_tokenizer_json_round_trip() {
+        let texts = [
+            "Hello, World! 你好世界",
+            "Ünïcödé naïve café résumé",
+            "[MASK] in the [CLS] middle of\tUNWANTED text",
+            "x\u{0}y \u{200b}zzzzzzzzzzz 1,234.5",
+            "[unused2] [unused1] [PAD] [MASK] [unused3]",
+        ];
+        let lowercase = FullTokenizer::builder("vocab.txt")
+            .added_tokens(vec!["[MASK]".to_string()])
+            .padding(PaddingParams {
+                strategy: PaddingStrategy::BatchLongest,
+                ..PaddingParams::default()
+            })
+            .build()
+            .unwrap();
+        let cased = FullTokenizer::builder("vocab.txt")
+            .do_lower_case(false)
+            .strip_accents(true)
+            .tokenize_chinese_chars(false)
+            .max_input_chars_per_word(5)
+            .build()
+            .unwrap();
+        let custom = FullTokenizer::builder("vocab.txt")
+            .special_tokens(SpecialTokens {
+                pad_token: Some("[unused3]".to_string()),
+                mask_token: Some("[unused1]".to_string()),
+                additional_special_tokens: vec!["[unused2]".to_string(), "[MASK]".to_string()],
+                ..SpecialTokens::default()
+            })
+            .added_tokens(vec!["[unused2]".to_string()])
+            .build()
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!(
+            "bert_tokenizer_round_trip_{}.json",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap();
+        for tokenizer in &[lowercase, cased, custom] {
+            tokenizer.save_tokenizer_json(path).unwrap();
+            let loaded = FullTokenizer::from_tokenizer_json(path).unwrap();
+            std::fs::remove_file(path).unwrap();
+
+            assert_eq!(loaded.vocab_size(), tokenizer.vocab_size());
+            assert_eq!(loaded.special_tokens(), tokenizer.special_tokens());
+            for text in &texts {
+                assert_eq!(loaded.tokenize(text), tokenizer.tokenize(text));
+            }
+            let pairs: Vec<_> = texts.iter().zip(texts.iter().rev()).collect();
+            let expected = tokenizer.encode_pair_batch(&pairs, 0).unwrap();
+            let encodings = loaded.encode_pair_batch(&pairs, 0).unwrap();
+            for (encoding, expected) in encodings.iter().zip(&expected) {
+                assert_eq!(encoding.input_ids, expected.input_ids);
+                assert_eq!(encoding.segment_ids, expected.segment_ids);
+                assert_eq!(encoding.offsets, expected.offsets);
+            }
+            for text in &texts {
+                let expected = tokenizer.encode(text, 32).unwrap();
+                let encoding = loaded.encode(text, 32).unwrap();
+                assert_eq!(encoding.input_ids, expected.input_ids);
+                assert_eq!(encoding.input_mask, expected.input_mask);
+                assert_eq!(encoding.special_tokens_mask, expected.special_tokens_mask);
+                assert_eq!(
+                    loaded.decode(&encoding.input_ids, true, false),
+                    tokenizer.decode(&expected.input_ids, true, false)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+
+use tokenization::BasicTokenizer;
+
+/// Lines counted per parallel batch by `feed_files`.
+const BATCH_LINES: usize = 10_000;
+
+/// Learns a WordPiece vocabulary from raw text.
+///
+/// Text is split into words by the same `BasicTokenizer` that
+/// `FullTokenizer` uses, so the options here must match the ones the
+/// vocabulary is loaded with. Subwords are learned by merging the most
+/// frequent pair of adjacent pieces, starting from single chars, as the
+/// WordPiece trainer of Hugging Face `tokenizers` does.
+///
+/// ```no_run
+/// # use bert_tokenizer::WordpieceTrainer;
+/// let mut trainer = WordpieceTrainer::new(21128)
+///     .min_frequency(2)
+///     .limit_alphabet(8000);
+/// trainer.feed_files(&["medical.txt", "legal.txt"]).unwrap();
+/// trainer.save("vocab.txt").unwrap();
+/// ```
+pub struct WordpieceTrainer {
+    vocab_size: usize,
+    min_frequency: u64,
+    limit_alphabet: Option<usize>,
+    special_tokens: Vec<String>,
+    num_unused: usize,
+    do_lower_case: bool,
+    strip_accents: Option<bool>,
+    tokenize_chinese_chars: bool,
+    max_input_chars_per_word: usize,
+    continuing_subword_prefix: String,
+    word_counts: HashMap<String, u64>,
+}
+
+impl WordpieceTrainer {
+    /// Targets a vocabulary of `vocab_size` tokens, special tokens and
+    /// unused slots included.
+    pub fn new(vocab_size: usize) -> WordpieceTrainer {
+        WordpieceTrainer {
+            vocab_size,
+            min_frequency: 2,
+            limit_alphabet: None,
+            special_tokens: ["[PAD]", "[UNK]", "[CLS]", "[SEP]", "[MASK]"]
+                .iter()
+                .map(|token| token.to_string())
+                .collect(),
+            num_unused: 100,
+            do_lower_case: true,
+            strip_accents: None,
+            tokenize_chinese_chars: true,
+            max_input_chars_per_word: 100,
+            continuing_subword_prefix: "##".to_string(),
+            word_counts: HashMap::new(),
+        }
+    }
+
+    /// Pairs of pieces seen fewer times are never merged. 2 by default.
+    pub fn min_frequency(mut self, min_frequency: u64) -> WordpieceTrainer {
+        self.min_frequency = min_frequency;
+        self
+    }
+
+    /// Keeps only the most frequent chars; words with any other char are
+    /// left out, as they could only become the unknown token. Unlimited by
+    /// default; the alphabet is cut further when its chars, alone and as
+    /// continuations, would not fit in `vocab_size`.
+    pub fn limit_alphabet(mut self, limit_alphabet: usize) -> WordpieceTrainer {
+        self.limit_alphabet = Some(limit_alphabet);
+        self
+    }
+
+    /// Tokens placed first in the vocabulary, in this order. `[PAD]`,
+    /// `[UNK]`, `[CLS]`, `[SEP]` and `[MASK]` by default.
+    pub fn special_tokens(mut self, special_tokens: Vec<String>) -> WordpieceTrainer {
+        self.special_tokens = special_tokens;
+        self
+    }
+
+    /// Number of `[unusedN]` slots after the special tokens, left for
+    /// tokens added when fine-tuning. 100 by default.
+    pub fn num_unused(mut self, num_unused: usize) -> WordpieceTrainer {
+        self.num_unused = num_unused;
+        self
+    }
+
+    pub fn do_lower_case(mut self, do_lower_case: bool) -> WordpieceTrainer {
+        self.do_lower_case = do_lower_case;
+        self
+    }
+
+    /// Strips accents independently of lowercasing. By default accents are
+    /// stripped exactly when lowercasing.
+    pub fn strip_accents(mut self, strip_accents: bool) -> WordpieceTrainer {
+        self.strip_accents = Some(strip_accents);
+        self
+    }
+
+    pub fn tokenize_chinese_chars(mut self, tokenize_chinese_chars: bool) -> WordpieceTrainer {
+        self.tokenize_chinese_chars = tokenize_chinese_chars;
+        self
+    }
+
+    /// Longer words are not counted, as `FullTokenizer` maps them to the
+    /// unknown token. 100 by default.
+    pub fn max_input_chars_per_word(mut self, max_input_chars_per_word: usize) -> WordpieceTrainer {
+        self.max_input_chars_per_word = max_input_chars_per_word;
+        self
+    }
+
+    pub fn continuing_subword_prefix<T: AsRef<str>>(mut self, prefix: T) -> WordpieceTrainer {
+        self.continuing_subword_prefix = prefix.as_ref().to_string();
+        self
+    }
+
+    fn basic_tokenizer(&self) -> BasicTokenizer {
+        BasicTokenizer {
+            do_lower_case: self.do_lower_case,
+            strip_accents: self.strip_accents.unwrap_or(self.do_lower_case),
+            tokenize_chinese_chars: self.tokenize_chinese_chars,
+        }
+    }
+
+    fn count_lines<T: AsRef<str> + Sync>(&self, lines: &[T]) -> HashMap<String, u64> {
+        let basic_tokenizer = self.basic_tokenizer();
+        let max_chars = self.max_input_chars_per_word;
+        lines
+            .par_iter()
+            .fold(HashMap::new, |mut counts, line| {
+                for word in basic_tokenizer.tokenize(line) {
+                    if word.chars().count() <= max_chars {
+                        *counts.entry(word).or_insert(0) += 1;
+                    }
+                }
+                counts
+            })
+            .reduce(HashMap::new, merge_counts)
+    }
+
+    fn add_counts(&mut self, counts: HashMap<String, u64>) {
+        let word_counts = std::mem::take(&mut self.word_counts);
+        self.word_counts = merge_counts(word_counts, counts);
+    }
+
+    /// Counts the words of `text`.
+    pub fn feed<T: AsRef<str>>(&mut self, text: T) {
+        let counts = self.count_lines(&[text.as_ref()]);
+        self.add_counts(counts);
+    }
+
+    /// Counts the words of every line of `files`, spreading the work over
+    /// the rayon thread pool.
+    pub fn feed_files<T: AsRef<str>>(&mut self, files: &[T]) -> Result<(), Box<dyn Error>> {
+        for file in files {
+            let reader = BufReader::new(File::open(file.as_ref())?);
+            let mut lines = Vec::with_capacity(BATCH_LINES);
+            for line in reader.lines() {
+                lines.push(line?);
+                if lines.len() == BATCH_LINES {
+                    let counts = self.count_lines(&lines);
+                    self.add_counts(counts);
+                    lines.clear();
+                }
+            }
+            let counts = self.count_lines(&lines);
+            self.add_counts(counts);
+        }
+        Ok(())
+    }
+
+    /// At most `limit` chars kept in the alphabet, most frequent first.
+    fn alphabet(&self, limit: usize) -> Vec<char> {
+        let mut char_counts = HashMap::new();
+        for (word, &count) in &self.word_counts {
+            for c in word.chars() {
+                *char_counts.entry(c).or_insert(0) += count;
+            }
+        }
+        let mut alphabet: Vec<(char, u64)> = char_counts.into_iter().collect();
+        alphabet.sort_by_key(|&(c, count)| (Reverse(count), c));
+        alphabet.truncate(self.limit_alphabet.unwrap_or(usize::MAX).min(limit));
+        alphabet.into_iter().map(|(c, _)| c).collect()
+    }
+
+    /// Returns the vocabulary learned from the words counted so far: the
+    /// special tokens, the unused slots, every char of the alphabet alone
+    /// and as a continuation, then the merged subwords in the order they
+    /// were learned.
+    ///
+    /// Fails when the special tokens and unused slots alone exceed
+    /// `vocab_size`.
+    pub fn train(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let prefix = &self.continuing_subword_prefix;
+        let mut vocab: Vec<String> = self.special_tokens.clone();
+        vocab.extend((0..self.num_unused).map(|i| format!("[unused{}]", i)));
+        if vocab.len() > self.vocab_size {
+            return Err(format!(
+                "{} special tokens and unused slots do not fit in a vocab of {}",
+                vocab.len(),
+                self.vocab_size
+            )
+            .into());
+        }
+        let mut ids: HashMap<String, usize> = HashMap::new();
+        for (id, token) in vocab.iter().enumerate() {
+            ids.entry(token.clone()).or_insert(id);
+        }
+
+        // every char takes two entries, alone and as a continuation
+        let mut alphabet = self.alphabet((self.vocab_size - vocab.len()) / 2);
+        alphabet.sort();
+        let mut add = |token: String, vocab: &mut Vec<String>| -> usize {
+            *ids.entry(token.clone()).or_insert_with(|| {
+                vocab.push(token);
+                vocab.len() - 1
+            })
+        };
+        for &c in &alphabet {
+            add(c.to_string(), &mut vocab);
+        }
+        for &c in &alphabet {
+            add(format!("{}{}", prefix, c), &mut vocab);
+        }
+
+        // every word as a sequence of vocabulary ids, in a fixed order so
+        // that ties between pairs are broken the same way on every run
+        let alphabet: HashSet<char> = alphabet.into_iter().collect();
+        let mut words: Vec<(&String, u64)> = self
+            .word_counts
+            .iter()
+            .filter(|(word, _)| word.chars().all(|c| alphabet.contains(&c)))
+            .map(|(word, &count)| (word, count))
+            .collect();
+        words.sort();
+        let counts: Vec<u64> = words.iter().map(|&(_, count)| count).collect();
+        let mut words: Vec<Vec<usize>> = words
+            .iter()
+            .map(|&(word, _)| {
+                word.chars()
+                    .enumerate()
+                    .map(|(i, c)| match i {
+                        0 => add(c.to_string(), &mut vocab),
+                        _ => add(format!("{}{}", prefix, c), &mut vocab),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut pair_counts: HashMap<(usize, usize), u64> = HashMap::new();
+        let mut pair_words: HashMap<(usize, usize), HashSet<usize>> = HashMap::new();
+        for (w, word) in words.iter().enumerate() {
+            for pair in word.windows(2) {
+                *pair_counts.entry((pair[0], pair[1])).or_insert(0) += counts[w];
+                pair_words.entry((pair[0], pair[1])).or_default().insert(w);
+            }
+        }
+        // a max-heap with stale entries, skipped when their count is outdated
+        let mut heap: BinaryHeap<(u64, Reverse<(usize, usize)>)> = pair_counts
+            .iter()
+            .map(|(&pair, &count)| (count, Reverse(pair)))
+            .collect();
+
+        while vocab.len() < self.vocab_size {
+            let (count, Reverse(pair)) = match heap.pop() {
+                Some(top) => top,
+                None => break,
+            };
+            if pair_counts.get(&pair) != Some(&count) {
+                continue;
+            }
+            if count < self.min_frequency.max(1) {
+                break;
+            }
+            let merged = format!("{}{}", vocab[pair.0], &vocab[pair.1][prefix.len()..]);
+            let merged = add(merged, &mut vocab);
+
+            let mut changed = HashSet::new();
+            let mut affected: Vec<usize> = pair_words
+                .remove(&pair)
+                .unwrap_or_default()
+                .into_iter()
+                .collect();
+            affected.sort_unstable();
+            for w in affected {
+                let word = &mut words[w];
+                let count = counts[w];
+                for pair in word.windows(2) {
+                    let pair = (pair[0], pair[1]);
+                    *pair_counts.get_mut(&pair).unwrap() -= count;
+                    changed.insert(pair);
+                }
+                let mut i = 0;
+                while i + 1 < word.len() {
+                    if (word[i], word[i + 1]) == pair {
+                        word[i] = merged;
+                        word.remove(i + 1);
+                    }
+                    i += 1;
+                }
+                for pair in word.windows(2) {
+                    let pair = (pair[0], pair[1]);
+                    *pair_counts.entry(pair).or_insert(0) += count;
+                    pair_words.entry(pair).or_default().insert(w);
+                    changed.insert(pair);
+                }
+            }
+            pair_counts.remove(&pair);
+            let mut changed: Vec<(usize, usize)> = changed.into_iter().collect();
+            changed.sort_unstable();
+            for pair in changed {
+                match pair_counts.get(&pair) {
+                    Some(&0) => {
+                        pair_counts.remove(&pair);
+                    }
+                    Some(&count) => heap.push((count, Reverse(pair))),
+                    None => {}
+                }
+            }
+        }
+        Ok(vocab)
+    }
+
+    /// Trains and writes the vocabulary to `vocab_file`, one token per line,
+    /// ready for `FullTokenizer::new`.
+    pub fn save<T: AsRef<str>>(&self, vocab_file: T) -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(vocab_file.as_ref())?);
+        for token in self.train()? {
+            writeln!(writer, "{}", token)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn merge_counts(a: HashMap<String, u64>, b: HashMap<String, u64>) -> HashMap<String, u64> {
+    let (mut larger, smaller) = if a.len() < b.len() { (b, a) } else { (a, b) };
+    for (word, count) in smaller {
+        *larger.entry(word).or_insert(0) += count;
+    }
+    larger
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokenization::FullTokenizer;
+
+    fn corpus() -> Vec<&'static str> {
+        let mut lines = Vec::new();
+        for _ in 0..20 {
+            lines.push("Unwanted running, wanting to run and runner ran.");
+            lines.push("患者主诉头痛头晕，头痛三天。");
+        }
+        lines.push("a rare 罕 word");
+        lines
+    }
+
+    #[test]
+    fn test_train() {
+        let mut trainer = WordpieceTrainer::new(70).num_unused(3);
+        for line in corpus() {
+            trainer.feed(line);
+        }
+        let vocab = trainer.train().unwrap();
+        assert_eq!(vocab.len(), 70);
+        assert_eq!(
+            vocab[..8],
+            [
+                "[PAD]",
+                "[UNK]",
+                "[CLS]",
+                "[SEP]",
+                "[MASK]",
+                "[unused0]",
+                "[unused1]",
+                "[unused2]"
+            ]
+        );
+        assert!(vocab.contains(&"o".to_string()) && vocab.contains(&"##o".to_string()));
+        assert!(vocab.contains(&"头".to_string()));
+        assert!(vocab.contains(&"runn".to_string()));
+
+        let path =
+            std::env::temp_dir().join(format!("bert_tokenizer_vocab_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        trainer.save(path).unwrap();
+        let tokenizer = FullTokenizer::new(path, true).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(tokenizer.vocab_size(), 70);
+        assert_eq!(tokenizer.pad_token_id(), Some(0));
+        assert_eq!(tokenizer.mask_token_id(), Some(4));
+        for line in corpus() {
+            assert!(!tokenizer.tokenize(line).contains(&"[UNK]".to_string()));
+        }
+        assert_eq!(
+            tokenizer.tokenize("Wanted running"),
+            ["want", "##e", "##d", "runn", "##ing"]
+        );
+    }
+
+    #[test]
+    fn test_train_limits() {
+        let mut trainer = WordpieceTrainer::new(1000)
+            .num_unused(0)
+            .min_frequency(100)
+            .limit_alphabet(20);
+        for line in corpus() {
+            trainer.feed(line);
+        }
+        let vocab = trainer.train().unwrap();
+        // "罕" is rarer than the 20 kept chars, and no pair occurs 100 times
+        assert!(!vocab.iter().any(|token| token.contains('罕')));
+        assert_eq!(vocab.len(), 5 + 2 * 20);
+
+        let vocab = WordpieceTrainer::new(1000).min_frequency(1).train();
+        assert_eq!(vocab.unwrap().len(), 105);
+
+        // without limit_alphabet the alphabet is cut to fit vocab_size
+        let mut trainer = WordpieceTrainer::new(21).num_unused(3);
+        for line in corpus() {
+            trainer.feed(line);
+        }
+        let vocab = trainer.train().unwrap();
+        // 6 chars take 12 of the 13 free entries and one merge the last
+        assert_eq!(vocab.len(), 21);
+        assert!(vocab.contains(&"n".to_string()) && vocab.contains(&"##n".to_string()));
+        assert!(!vocab.iter().any(|token| token.contains('罕')));
+
+        assert!(WordpieceTrainer::new(104).train().is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use tokenization::Vocab;
+
+const NONE: u32 = u32::MAX;
+const ROOT: u32 = 0;
+const SUFFIX_ROOT: u32 = 1;
+
+struct Node {
+    /// Vocabulary id if the path to this node spells a token.
+    token_id: Option<usize>,
+    /// Number of chars on the path from the root of this node's trie.
+    depth: usize,
+    /// Node to continue from once the failure pops have been emitted.
+    failure_link: u32,
+    /// Range of `WordpieceTrie::failure_pops` emitted when following the
+    /// failure link.
+    failure_pops: (usize, usize),
+}
+
+/// Vocabulary trie implementing the LinMaxMatch algorithm of "Fast WordPiece
+/// Tokenization" (Song et al., 2021).
+///
+/// Word-initial tokens hang off `ROOT`; continuation tokens hang off
+/// `SUFFIX_ROOT` with their prefix removed. Every node carries a failure link
+/// and the tokens greedy longest-match-first emits before resuming at that
+/// link, so a word is tokenized in a single left-to-right pass without
+/// backtracking.
+pub(crate) struct WordpieceTrie {
+    nodes: Vec<Node>,
+    edges: HashMap<(u32, char), u32>,
+    /// `(token id, token length in chars)` of every failure pop.
+    failure_pops: Vec<(usize, usize)>,
+}
+
+impl WordpieceTrie {
+    pub fn new(vocab: &Vocab, suffix_prefix: &str) -> WordpieceTrie {
+        let mut trie = WordpieceTrie {
+            nodes: Vec::new(),
+            edges: HashMap::new(),
+            failure_pops: Vec::new(),
+        };
+        let mut children: Vec<Vec<(char, u32)>> = Vec::new();
+        for _ in 0..2 {
+            trie.add_node(0, &mut children);
+        }
+
+        for (token, &id) in vocab {
+            if token.is_empty() {
+                continue;
+            }
+            // every token may start a word, as the naive algorithm looks up
+            // word-initial substrings without the prefix
+            trie.insert(ROOT, token, id, &mut children);
+            if token.len() > suffix_prefix.len() && token.starts_with(suffix_prefix) {
+                trie.insert(
+                    SUFFIX_ROOT,
+                    &token[suffix_prefix.len()..],
+                    id,
+                    &mut children,
+                );
+            }
+        }
+        trie.build_failure_links(&children);
+        trie
+    }
+
+    fn add_node(&mut self, depth: usize, children: &mut Vec<Vec<(char, u32)>>) -> u32 {
+        self.nodes.push(Node {
+            token_id: None,
+            depth,
+            failure_link: NONE,
+            failure_pops: (0, 0),
+        });
+        children.push(Vec::new());
+        (self.nodes.len() - 1) as u32
+    }
+
+    fn insert(&mut self, root: u32, token: &str, id: usize, children: &mut Vec<Vec<(char, u32)>>) {
+        let mut node = root;
+        for c in token.chars() {
+            node = match self.edges.get(&(node, c)) {
+                Some(&child) => child,
+                None => {
+                    let depth = self.nodes[node as usize].depth + 1;
+                    let child = self.add_node(depth, children);
+                    self.edges.insert((node, c), child);
+                    children[node as usize].push((c, child));
+                    child
+                }
+            };
+        }
+        self.nodes[node as usize].token_id = Some(id);
+    }
+
+    /// Computes failure links and failure pops breadth-first, so the links of
+    /// every shallower node are known when a node is visited.
+    fn build_failure_links(&mut self, children: &[Vec<(char, u32)>]) {
+        let mut queue = VecDeque::new();
+        for &root in &[ROOT, SUFFIX_ROOT] {
+            for &(c, child) in &children[root as usize] {
+                queue.push_back((root, c, child));
+            }
+        }
+
+        while let Some((parent, c, node)) = queue.pop_front() {
+            let start = self.failure_pops.len();
+            if let Some(id) = self.nodes[node as usize].token_id {
+                let depth = self.nodes[node as usize].depth;
+                self.failure_pops.push((id, depth));
+                self.nodes[node as usize].failure_link = SUFFIX_ROOT;
+            } else {
+                let (parent_start, parent_end) = self.nodes[parent as usize].failure_pops;
+                for i in parent_start..parent_end {
+                    let pop = self.failure_pops[i];
+                    self.failure_pops.push(pop);
+                }
+                let mut link = self.nodes[parent as usize].failure_link;
+                while link != NONE && !self.edges.contains_key(&(link, c)) {
+                    let (link_start, link_end) = self.nodes[link as usize].failure_pops;
+                    for i in link_start..link_end {
+                        let pop = self.failure_pops[i];
+                        self.failure_pops.push(pop);
+                    }
+                    link = self.nodes[link as usize].failure_link;
+                }
+                if link == NONE {
+                    // the word cannot be tokenized once it gets stuck here
+                    self.failure_pops.truncate(start);
+                } else {
+                    self.nodes[node as usize].failure_link = self.edges[&(link, c)];
+                }
+            }
+            let end = self.failure_pops.len();
+            self.nodes[node as usize].failure_pops = (start, end);
+
+            for &(c, child) in &children[node as usize] {
+                queue.push_back((node, c, child));
+            }
+        }
+    }
+
+    fn follow_failure_link(&self, node: u32, output: &mut Vec<(usize, usize, usize)>) -> u32 {
+        let (start, end) = self.nodes[node as usize].failure_pops;
+        for &(id, len) in &self.failure_pops[start..end] {
+            let offset = output.last().map_or(0, |&(_, _, end)| end);
+            output.push((id, offset, offset + len));
+        }
+        self.nodes[node as usize].failure_link
+    }
+
+    /// Greedy longest-match-first split of a single word, returning the
+    /// `(id, start, end)` char span of every piece, or `None` if the word
+    /// cannot be split into vocabulary tokens.
+    pub fn tokenize_word(&self, chars: &[char]) -> Option<Vec<(usize, usize, usize)>> {
+        let mut output = Vec::new();
+        let mut node = ROOT;
+        for &c in chars {
+            loop {
+                if let Some(&child) = self.edges.get(&(node, c)) {
+                    node = child;
+                    break;
+                }
+                node = self.follow_failure_link(node, &mut output);
+                if node == NONE {
+                    return None;
+                }
+            }
+        }
+        while node != ROOT && node != SUFFIX_ROOT {
+            node = self.follow_failure_link(node, &mut output);
+            if node == NONE {
+                return None;
+            }
+        }
+        Some(output)
+    }
+}
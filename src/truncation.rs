@@ -0,0 +1,183 @@
+use std::error::Error;
+
+/// How a sequence or sequence pair is cut down to `max_seq_len`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TruncationStrategy {
+    /// Drops the last token of the longer sequence until the pair fits, as
+    /// the original BERT `truncate_seq_pair`. The default.
+    #[default]
+    LongestFirst,
+    /// Only drops tokens from the end of the first sequence.
+    OnlyFirst,
+    /// Only drops tokens from the end of the second sequence.
+    OnlySecond,
+    /// Fails when the input does not fit.
+    DoNotTruncate,
+    /// Drops tokens from the middle instead of the end: a truncated sequence
+    /// keeps up to its first `head` and last `tail` tokens. Pairs share the
+    /// budget as in `LongestFirst`; when `head` and `tail` exceed the share
+    /// of a sequence, the head is kept and the tail shortened.
+    HeadTail { head: usize, tail: usize },
+}
+
+fn too_long(len: usize, max_len: usize) -> Box<dyn Error + Send + Sync> {
+    format!(
+        "sequence of {} tokens does not fit in {} tokens",
+        len, max_len
+    )
+    .into()
+}
+
+/// Number of tokens each sequence has to lose for both to fit in `max_len`
+/// when popping from the longer one.
+fn longest_first_counts(len_a: usize, len_b: usize, max_len: usize) -> (usize, usize) {
+    let (mut len_a, mut len_b) = (len_a, len_b);
+    let (mut dropped_a, mut dropped_b) = (0, 0);
+    while len_a + len_b > max_len {
+        if len_a > len_b {
+            len_a -= 1;
+            dropped_a += 1;
+        } else {
+            len_b -= 1;
+            dropped_b += 1;
+        }
+    }
+    (dropped_a, dropped_b)
+}
+
+/// Removes at least `count` tokens from the middle, keeping up to the first
+/// `head` and last `tail` tokens. Returns the number of tokens removed.
+fn remove_middle<T>(tokens: &mut Vec<T>, head: usize, tail: usize, count: usize) -> usize {
+    if count == 0 {
+        return 0;
+    }
+    let keep = (tokens.len() - count).min(head.saturating_add(tail));
+    let removed = tokens.len() - keep;
+    let head = head.min(keep);
+    tokens.drain(head..head + removed);
+    removed
+}
+
+/// Truncates `tokens_a` and `tokens_b` to at most `max_len` tokens in total.
+///
+/// Returns the number of tokens dropped from each sequence.
+pub(crate) fn truncate_seq_pair<T>(
+    strategy: TruncationStrategy,
+    tokens_a: &mut Vec<T>,
+    tokens_b: &mut Vec<T>,
+    max_len: usize,
+) -> Result<(usize, usize), Box<dyn Error + Send + Sync>> {
+    let total = tokens_a.len() + tokens_b.len();
+    if total <= max_len {
+        return Ok((0, 0));
+    }
+    let excess = total - max_len;
+    let (dropped_a, dropped_b) = match strategy {
+        TruncationStrategy::LongestFirst => {
+            let counts = longest_first_counts(tokens_a.len(), tokens_b.len(), max_len);
+            tokens_a.truncate(tokens_a.len() - counts.0);
+            tokens_b.truncate(tokens_b.len() - counts.1);
+            counts
+        }
+        TruncationStrategy::OnlyFirst => {
+            if excess > tokens_a.len() {
+                return Err(too_long(total, max_len));
+            }
+            tokens_a.truncate(tokens_a.len() - excess);
+            (excess, 0)
+        }
+        TruncationStrategy::OnlySecond => {
+            if excess > tokens_b.len() {
+                return Err(too_long(total, max_len));
+            }
+            tokens_b.truncate(tokens_b.len() - excess);
+            (0, excess)
+        }
+        TruncationStrategy::DoNotTruncate => return Err(too_long(total, max_len)),
+        TruncationStrategy::HeadTail { head, tail } => {
+            let counts = longest_first_counts(tokens_a.len(), tokens_b.len(), max_len);
+            (
+                remove_middle(tokens_a, head, tail, counts.0),
+                remove_middle(tokens_b, head, tail, counts.1),
+            )
+        }
+    };
+    Ok((dropped_a, dropped_b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    type Truncated = (Vec<usize>, Vec<usize>, (usize, usize));
+
+    fn truncate(
+        strategy: TruncationStrategy,
+        len_a: usize,
+        len_b: usize,
+        max_len: usize,
+    ) -> Option<Truncated> {
+        let mut tokens_a: Vec<usize> = (0..len_a).collect();
+        let mut tokens_b: Vec<usize> = (100..100 + len_b).collect();
+        let counts = truncate_seq_pair(strategy, &mut tokens_a, &mut tokens_b, max_len).ok()?;
+        Some((tokens_a, tokens_b, counts))
+    }
+
+    #[test]
+    fn test_longest_first() {
+        let (a, b, counts) = truncate(TruncationStrategy::LongestFirst, 5, 3, 5).unwrap();
+        assert_eq!(a, [0, 1, 2]);
+        assert_eq!(b, [100, 101]);
+        assert_eq!(counts, (2, 1));
+
+        let (a, b, counts) = truncate(TruncationStrategy::LongestFirst, 2, 2, 5).unwrap();
+        assert_eq!((a.len(), b.len(), counts), (2, 2, (0, 0)));
+    }
+
+    #[test]
+    fn test_only_first_and_second() {
+        let (a, b, counts) = truncate(TruncationStrategy::OnlyFirst, 5, 3, 5).unwrap();
+        assert_eq!(a, [0, 1]);
+        assert_eq!(b, [100, 101, 102]);
+        assert_eq!(counts, (3, 0));
+        assert!(truncate(TruncationStrategy::OnlyFirst, 1, 6, 5).is_none());
+
+        let (a, b, counts) = truncate(TruncationStrategy::OnlySecond, 4, 3, 5).unwrap();
+        assert_eq!(a, [0, 1, 2, 3]);
+        assert_eq!(b, [100]);
+        assert_eq!(counts, (0, 2));
+        assert!(truncate(TruncationStrategy::OnlySecond, 6, 0, 5).is_none());
+    }
+
+    #[test]
+    fn test_do_not_truncate() {
+        assert!(truncate(TruncationStrategy::DoNotTruncate, 3, 3, 6).is_some());
+        assert!(truncate(TruncationStrategy::DoNotTruncate, 4, 3, 6).is_none());
+    }
+
+    #[test]
+    fn test_head_tail() {
+        let strategy = TruncationStrategy::HeadTail { head: 2, tail: 3 };
+        let (a, b, counts) = truncate(strategy, 10, 0, 5).unwrap();
+        assert_eq!(a, [0, 1, 7, 8, 9]);
+        assert!(b.is_empty());
+        assert_eq!(counts, (5, 0));
+
+        let (a, b, counts) = truncate(strategy, 6, 4, 6).unwrap();
+        assert_eq!(a, [0, 1, 5]);
+        assert_eq!(b, [100, 101, 103]);
+        assert_eq!(counts, (3, 1));
+
+        // a truncated sequence keeps no more than its head and tail
+        let strategy = TruncationStrategy::HeadTail { head: 1, tail: 1 };
+        let (a, b, counts) = truncate(strategy, 10, 3, 8).unwrap();
+        assert_eq!(a, [0, 9]);
+        assert_eq!(b, [100, 101, 102]);
+        assert_eq!(counts, (8, 0));
+
+        // a head longer than the budget keeps only the head
+        let strategy = TruncationStrategy::HeadTail { head: 8, tail: 2 };
+        let (a, _, _) = truncate(strategy, 10, 0, 3).unwrap();
+        assert_eq!(a, [0, 1, 2]);
+    }
+}